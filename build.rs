@@ -7,6 +7,7 @@ fn main() {
         .input("src/protos/activate.proto")
         .input("src/protos/subscribe.proto")
         .input("src/protos/providerstate.proto")
+        .input("src/protos/hello.proto")
         .include("src/protos")
         .run_from_script();
 }
@@ -0,0 +1,102 @@
+//! `GAction`s exported on the application, so walker can be driven over D-Bus,
+//! f.e. `gapplication action dev.benz.walker open-provider "'files'"`.
+
+use gtk4::gio::ActionEntry;
+use gtk4::glib::{Variant, VariantTy};
+use gtk4::prelude::*;
+use gtk4::{Application, gio::SimpleAction};
+
+use crate::commands::{close, open, reload_config};
+use crate::state::{get_theme, has_theme, is_visible, set_theme};
+use crate::theme::setup_css;
+use crate::ui::window::{quit, set_input_text};
+
+pub fn setup_app_actions(app: &Application) {
+    let open_provider = ActionEntry::builder("open-provider")
+        .parameter_type(Some(VariantTy::STRING))
+        .activate(
+            |app: &Application, _: &SimpleAction, param: Option<&Variant>| {
+                report(open(app, string_param(param), None, None, None));
+            },
+        )
+        .build();
+
+    let open_set = ActionEntry::builder("open-set")
+        .parameter_type(Some(VariantTy::STRING))
+        .activate(
+            |app: &Application, _: &SimpleAction, param: Option<&Variant>| {
+                report(open(app, None, string_param(param), None, None));
+            },
+        )
+        .build();
+
+    let set_query = ActionEntry::builder("set-query")
+        .parameter_type(Some(VariantTy::STRING))
+        .activate(
+            |app: &Application, _: &SimpleAction, param: Option<&Variant>| {
+                let query = string_param(param).unwrap_or_default();
+
+                if is_visible() {
+                    set_input_text(&query);
+                } else {
+                    report(open(app, None, None, None, Some(query)));
+                }
+            },
+        )
+        .build();
+
+    let close_action = ActionEntry::builder("close")
+        .activate(|app: &Application, _: &SimpleAction, _: Option<&Variant>| close(app))
+        .build();
+
+    let reload = ActionEntry::builder("reload")
+        .activate(|_: &Application, _: &SimpleAction, _: Option<&Variant>| {
+            // reloading the config sets up the themes again already
+            report(reload_config());
+            setup_css(get_theme());
+        })
+        .build();
+
+    let switch_theme = ActionEntry::builder("switch-theme")
+        .parameter_type(Some(VariantTy::STRING))
+        .activate(
+            |app: &Application, _: &SimpleAction, param: Option<&Variant>| {
+                let theme = string_param(param).unwrap_or_default();
+
+                if !has_theme(&theme) {
+                    eprintln!("theme '{theme}' not found");
+                    return;
+                }
+
+                // every theme has its own window, so reopen to show the new one
+                if is_visible() {
+                    quit(app, false);
+                    set_theme(theme);
+                    report(open(app, None, None, None, None));
+                } else {
+                    set_theme(theme.clone());
+                    setup_css(theme);
+                }
+            },
+        )
+        .build();
+
+    app.add_action_entries([
+        open_provider,
+        open_set,
+        set_query,
+        close_action,
+        reload,
+        switch_theme,
+    ]);
+}
+
+fn string_param(param: Option<&Variant>) -> Option<String> {
+    param.and_then(Variant::str).map(str::to_string)
+}
+
+fn report(result: Result<(), String>) {
+    if let Err(e) = result {
+        eprintln!("{e}");
+    }
+}
@@ -0,0 +1,253 @@
+//! Line-delimited JSON commands on `walker.sock`.
+//!
+//! Every line is one command, f.e. `{"command":"open","provider":"files"}`,
+//! answered by one response line. Clients that close the connection without
+//! sending anything, or send an empty line first, toggle walker, like before
+//! commands existed.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+
+use gtk4::{Application, glib};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::data::refresh_providers;
+use crate::providers::known_providers;
+use crate::state::{
+    get_current_set, get_provider, get_query, get_theme, has_elephant, has_theme, is_connected,
+    is_dmenu, is_password, is_service, is_visible, set_current_set, set_provider, set_theme,
+};
+use crate::theme::{setup_css, setup_themes};
+use crate::ui::window::{quit, set_input_text, with_window};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Command {
+    Open {
+        provider: Option<String>,
+        set: Option<String>,
+        theme: Option<String>,
+        query: Option<String>,
+    },
+    Close,
+    Toggle,
+    ReloadConfig,
+    ReloadTheme,
+    GetState,
+}
+
+#[derive(Debug, Serialize)]
+struct CommandResponse {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<StateSnapshot>,
+}
+
+impl CommandResponse {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            state: None,
+        }
+    }
+
+    fn error(msg: String) -> Self {
+        Self {
+            ok: false,
+            error: Some(msg),
+            state: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StateSnapshot {
+    visible: bool,
+    connected: bool,
+    provider: String,
+    set: String,
+    theme: String,
+    query: String,
+}
+
+/// Serve one client. Blocks, so run it off the main thread.
+pub fn serve_client(stream: UnixStream) {
+    if let Err(e) = handle_client(stream) {
+        eprintln!("walker.sock client error: {e}");
+    }
+}
+
+fn handle_client(stream: UnixStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    let mut first = true;
+
+    loop {
+        line.clear();
+
+        let read = reader.read_line(&mut line)?;
+
+        // closing the connection or sending an empty line before any command toggles
+        if read == 0 || (first && line.trim().is_empty()) {
+            if first {
+                run_on_main(Command::Toggle);
+            }
+
+            return Ok(());
+        }
+
+        first = false;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Command>(&line) {
+            Ok(cmd) => run_on_main(cmd),
+            Err(e) => CommandResponse::error(format!("invalid command: {e}")),
+        };
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+}
+
+fn run_on_main(cmd: Command) -> CommandResponse {
+    let (sender, receiver) = mpsc::channel();
+
+    glib::idle_add_once(move || {
+        let _ = sender.send(handle_command(cmd));
+    });
+
+    receiver
+        .recv()
+        .unwrap_or_else(|_| CommandResponse::error("walker is shutting down".to_string()))
+}
+
+fn handle_command(cmd: Command) -> CommandResponse {
+    let app = with_window(|w| w.app.clone());
+
+    let result = match cmd {
+        Command::Open {
+            provider,
+            set,
+            theme,
+            query,
+        } => open(&app, provider, set, theme, query),
+        Command::Close => {
+            close(&app);
+            Ok(())
+        }
+        Command::Toggle => {
+            crate::reset_parameters();
+            crate::activate(&app);
+            Ok(())
+        }
+        Command::ReloadConfig => reload_config(),
+        Command::ReloadTheme => {
+            reload_theme();
+            Ok(())
+        }
+        Command::GetState => {
+            return CommandResponse {
+                state: Some(StateSnapshot {
+                    visible: is_visible(),
+                    connected: is_connected(),
+                    provider: get_provider(),
+                    set: get_current_set(),
+                    theme: get_theme(),
+                    query: if is_password() {
+                        String::new()
+                    } else {
+                        get_query()
+                    },
+                }),
+                ..CommandResponse::ok()
+            };
+        }
+    };
+
+    match result {
+        Ok(()) => CommandResponse::ok(),
+        Err(e) => CommandResponse::error(e),
+    }
+}
+
+/// Open walker with the given provider, set, theme and query. If walker is
+/// already open, it switches to them instead of closing.
+pub fn open(
+    app: &Application,
+    provider: Option<String>,
+    set: Option<String>,
+    theme: Option<String>,
+    query: Option<String>,
+) -> Result<(), String> {
+    if let Some(theme) = &theme
+        && !has_theme(theme)
+    {
+        return Err(format!("theme '{theme}' not found"));
+    }
+
+    if let Some(set) = &set
+        && !config::get_config().providers.sets.contains_key(set)
+    {
+        return Err(format!("set '{set}' not found"));
+    }
+
+    let was_visible = is_visible();
+
+    // the parameters belong to the running dmenu call
+    if was_visible && is_dmenu() {
+        return Err("a dmenu call is open".to_string());
+    }
+
+    crate::reset_parameters();
+    set_provider(provider.unwrap_or_default());
+    set_current_set(set.unwrap_or_default());
+
+    if let Some(theme) = theme {
+        set_theme(theme);
+    }
+
+    if !was_visible {
+        crate::activate(app);
+    }
+
+    match query {
+        Some(query) => set_input_text(&query),
+        None if was_visible => set_input_text(&get_query()),
+        None => (),
+    }
+
+    Ok(())
+}
+
+pub fn close(app: &Application) {
+    if is_visible() {
+        quit(app, false);
+    }
+}
+
+/// Reload the config and everything derived from it.
+pub fn reload_config() -> Result<(), String> {
+    config::load().map_err(|e| e.to_string())?;
+    crate::reload_providers(known_providers())?;
+
+    if has_elephant() {
+        refresh_providers();
+    }
+
+    Ok(())
+}
+
+/// Reload themes from disk and apply the current theme's css.
+pub fn reload_theme() {
+    setup_themes(has_elephant(), get_theme(), is_service());
+    setup_css(get_theme());
+}
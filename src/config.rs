@@ -1,10 +1,17 @@
 use config::{Config, ConfigError, File, FileFormat};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::OnceLock};
-
-use crate::{keybinds::Action, state::set_error};
-
-static LOADED_CONFIG: OnceLock<Walker> = OnceLock::new();
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    keybinds::Action,
+    matching::{CaseMode, MatchMode},
+};
+
+static LOADED_CONFIG: RwLock<Option<Arc<Walker>>> = RwLock::new(None);
 const DEFAULT_CONFIG: &str = include_str!("../resources/config.toml");
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +38,8 @@ pub struct Walker {
     pub global_argument_delimiter: String,
     pub theme: String,
     pub exact_search_prefix: String,
+    pub elephant_socket: String,
+    pub highlight_markup: String,
     pub providers: Providers,
     pub installed_providers: Option<Vec<String>>,
     pub emergencies: Option<Vec<EmergencyEntry>>,
@@ -40,6 +49,9 @@ pub struct Walker {
     pub placeholders: Option<HashMap<String, Placeholder>>,
     pub columns: Option<HashMap<String, u32>>,
     pub page_jump_items: u32,
+    pub query_debounce: u64,
+    pub match_mode: MatchMode,
+    pub match_case: CaseMode,
 }
 
 // Partial config for user overrides
@@ -81,6 +93,10 @@ struct PartialWalker {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub exact_search_prefix: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub elephant_socket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_markup: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub providers: Option<PartialProviders>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub installed_providers: Option<Vec<String>>,
@@ -96,6 +112,12 @@ struct PartialWalker {
     pub columns: Option<HashMap<String, u32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub page_jump_items: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_debounce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_mode: Option<MatchMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_case: Option<CaseMode>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -121,6 +143,8 @@ struct PartialProviders {
     pub max_results_provider: Option<HashMap<String, i32>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub argument_delimiter: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscriptions: Option<HashMap<String, Subscription>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -150,6 +174,12 @@ struct PartialKeybinds {
     pub page_down: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub page_up: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_connection: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toggle_mark: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cycle_match_mode: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -173,26 +203,27 @@ struct PartialClipboard {
 }
 
 impl Walker {
-    pub fn new() -> Result<Self, ConfigError> {
+    /// The defaults merged with the user config and the environment. A user
+    /// config that can't be parsed is left out, its error is returned alongside.
+    pub fn new() -> Result<(Self, Option<ConfigError>), ConfigError> {
         let default_config = Config::builder()
             .add_source(File::from_str(DEFAULT_CONFIG, FileFormat::Toml))
             .build()?;
 
         let mut config: Walker = default_config.try_deserialize()?;
+        let mut user_error = None;
 
         if let Some(user_config_path) =
             xdg::BaseDirectories::with_prefix("walker").find_config_file("config.toml")
         {
             let user_config = Config::builder()
                 .add_source(File::from(user_config_path))
-                .build()?;
+                .build()
+                .and_then(Config::try_deserialize::<PartialWalker>);
 
-            match user_config.try_deserialize() {
+            match user_config {
                 Ok(res) => config.merge(res),
-                Err(error) => {
-                    set_error(format!("Config: {error}"));
-                    println!("{error}");
-                }
+                Err(error) => user_error = Some(error),
             }
         }
 
@@ -204,7 +235,7 @@ impl Walker {
             config.merge(partial);
         }
 
-        Ok(config)
+        Ok((config, user_error))
     }
 
     fn merge(&mut self, partial: PartialWalker) {
@@ -259,6 +290,12 @@ impl Walker {
         if let Some(v) = partial.exact_search_prefix {
             self.exact_search_prefix = v;
         }
+        if let Some(v) = partial.elephant_socket {
+            self.elephant_socket = v;
+        }
+        if let Some(v) = partial.highlight_markup {
+            self.highlight_markup = v;
+        }
         if let Some(v) = partial.installed_providers {
             self.installed_providers = Some(v);
         }
@@ -274,6 +311,15 @@ impl Walker {
         if let Some(v) = partial.page_jump_items {
             self.page_jump_items = v;
         }
+        if let Some(v) = partial.query_debounce {
+            self.query_debounce = v;
+        }
+        if let Some(v) = partial.match_mode {
+            self.match_mode = v;
+        }
+        if let Some(v) = partial.match_case {
+            self.match_case = v;
+        }
 
         if let Some(p) = partial.providers {
             self.providers.merge(p);
@@ -321,6 +367,12 @@ impl Providers {
             });
         }
 
+        if let Some(v) = partial.subscriptions {
+            v.iter().for_each(|(key, value)| {
+                self.subscriptions.insert(key.clone(), value.clone());
+            });
+        }
+
         if let Some(v) = partial.actions {
             v.iter().for_each(|(key, value)| {
                 if !self.actions.contains_key(key) {
@@ -392,6 +444,15 @@ impl Keybinds {
         if let Some(v) = partial.page_up {
             self.page_up = v;
         }
+        if let Some(v) = partial.retry_connection {
+            self.retry_connection = v;
+        }
+        if let Some(v) = partial.toggle_mark {
+            self.toggle_mark = v;
+        }
+        if let Some(v) = partial.cycle_match_mode {
+            self.cycle_match_mode = v;
+        }
     }
 }
 
@@ -449,6 +510,9 @@ pub struct Keybinds {
     pub quick_activate: Option<Vec<String>>,
     pub page_down: Vec<String>,
     pub page_up: Vec<String>,
+    pub retry_connection: Vec<String>,
+    pub toggle_mark: Vec<String>,
+    pub cycle_match_mode: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -463,6 +527,7 @@ pub struct Providers {
     pub clipboard: Clipboard,
     pub actions: HashMap<String, Vec<Action>>,
     pub sets: HashMap<String, ProviderSet>,
+    pub subscriptions: HashMap<String, Subscription>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -471,6 +536,14 @@ pub struct ProviderSet {
     pub empty: Vec<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    #[serde(default)]
+    pub interval: i32,
+    #[serde(default)]
+    pub query: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Prefix {
     pub prefix: String,
@@ -482,12 +555,48 @@ pub struct Clipboard {
     pub time_format: String,
 }
 
-pub fn load() -> Result<(), Box<dyn std::error::Error>> {
-    LOADED_CONFIG
-        .set(Walker::new()?)
-        .map_err(|_| "Failed to set loaded config".into())
+#[derive(Debug)]
+pub enum LoadError {
+    /// The defaults or the environment couldn't be loaded, nothing changed.
+    Config(ConfigError),
+    /// The user config couldn't be parsed and was left out.
+    UserConfig(ConfigError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Config(e) => write!(f, "couldn't load config: {e}"),
+            LoadError::UserConfig(e) => write!(f, "couldn't parse user config: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Load the config. Calling it again replaces the config for all later `get_config` calls.
+///
+/// If the user config can't be parsed, the defaults are loaded in its place the
+/// first time. Later calls keep the config loaded before.
+pub fn load() -> Result<(), LoadError> {
+    let (config, user_error) = Walker::new().map_err(LoadError::Config)?;
+    let mut loaded = LOADED_CONFIG.write().unwrap();
+
+    if user_error.is_none() || loaded.is_none() {
+        *loaded = Some(Arc::new(config));
+    }
+
+    match user_error {
+        Some(e) => Err(LoadError::UserConfig(e)),
+        None => Ok(()),
+    }
 }
 
-pub fn get_config() -> &'static Walker {
-    LOADED_CONFIG.get().expect("config not initialized")
+/// The current config. A reload doesn't affect configs already handed out.
+pub fn get_config() -> Arc<Walker> {
+    LOADED_CONFIG
+        .read()
+        .unwrap()
+        .clone()
+        .expect("config not initialized")
 }
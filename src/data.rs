@@ -1,70 +1,126 @@
-use crate::config::get_config;
+use crate::config::{Prefix, get_config};
+use crate::dmenu::{DmenuResult, accept_result};
 use crate::keybinds::{Action, AfterAction};
+use crate::protocol::{
+    Features, FrameError, PROTOCOL_VERSION, Request, Response, hello, read_response,
+    read_subscription_update, write_request,
+};
 use crate::protos::generated_proto::activate::ActivateRequest;
-use crate::protos::generated_proto::providerstate::{ProviderStateRequest, ProviderStateResponse};
+use crate::protos::generated_proto::hello::HelloResponse;
+use crate::protos::generated_proto::providerstate::ProviderStateRequest;
 use crate::protos::generated_proto::query::{QueryRequest, QueryResponse, query_response};
 use crate::protos::generated_proto::subscribe::SubscribeRequest;
-use crate::protos::generated_proto::subscribe::SubscribeResponse;
-use crate::providers::PROVIDERS;
+use crate::providers::{get_providers, has_providers};
 use crate::state::{
-    get_action_menu_query, get_async_after, get_current_prefix, get_current_set, get_error,
-    get_prefix_provider, get_provider, is_actions_menu, is_connected, is_connecting, is_dmenu,
-    is_emergency, is_index, is_service, set_async_after, set_current_prefix, set_error,
-    set_global_provider_actions, set_global_provider_state, set_is_connected, set_is_connecting,
-    set_is_emergency, set_is_visible, set_prefix_provider, set_provider, set_query,
+    get_action_menu_query, get_async_after, get_connection_state, get_current_prefix,
+    get_current_set, get_dmenu_accept_nth, get_dmenu_delimiter, get_error, get_prefix_provider,
+    get_provider, is_actions_menu, is_connected, is_dmenu, is_emergency, is_index, is_multi,
+    is_service, set_async_after, set_connection_state, set_current_prefix, set_error, set_features,
+    set_global_provider_state, set_is_emergency, set_prefix_provider, set_provider, set_query,
 };
+use crate::subscriptions::{dispatch, resubscribe};
+use crate::ui::local_filter::{filter_local_items, is_local};
 use crate::ui::window::{
-    check_error, handle_changed_items, reset_actions_menu, set_input_text, set_keybind_hint,
-    with_window,
+    check_error, get_marked_items, handle_changed_items, reset_actions_menu, set_input_text,
+    set_keybind_hint, show_connection_state, with_window,
 };
 use crate::{QueryResponseObject, send_message};
-use gtk4::glib::Object;
 use gtk4::{glib, prelude::*};
-use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
-use nucleo_matcher::{Config, Matcher};
-use protobuf::{Message, MessageField};
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::io::{BufReader, Read, Write};
-use std::os::unix::net::UnixStream;
-use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream as StdUnixStream};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering as AtomicOrdering};
+use std::sync::{LazyLock, Mutex, OnceLock};
 use std::time::Duration;
-use std::{env, thread};
+use std::{env, io};
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::net::unix::OwnedReadHalf;
+use tokio::runtime::{self, Runtime};
+use tokio::sync::{Notify, mpsc, watch};
+use tokio::task::JoinSet;
+
+/// Requests waiting to be written. Further requests are dropped until the
+/// writer catches up.
+const REQUEST_QUEUE_LEN: usize = 64;
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(32);
+/// Elephant answers the hello request right away, and versions without the
+/// exchange give themselves away by closing the connection or sending another
+/// frame. This only guards against a peer that does neither, once per endpoint.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+    runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("elephant")
+        .enable_all()
+        .build()
+        .expect("failed to start tokio runtime")
+});
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+/// Endpoint that didn't answer the hello request, later connections to it skip it.
+static LEGACY_ENDPOINT: Mutex<Option<String>> = Mutex::new(None);
+static RETRY_NOW: Notify = Notify::const_new();
+
+/// Changes to the results received since the last flush. They are applied in
+/// order once per frame, or as soon as elephant marks the results as done.
+static PENDING_CHANGES: Mutex<Vec<PendingChange>> = Mutex::new(Vec::new());
+static FLUSH_SCHEDULED: AtomicBool = AtomicBool::new(false);
+const BATCH_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Id of the latest query written to the current connection. Elephant echoes
+/// the id in its results, so results tagged with an older id are discarded.
+static LATEST_QID: AtomicI32 = AtomicI32::new(0);
+
+enum PendingChange {
+    Item(QueryResponse),
+    Update(QueryResponse),
+    Clear,
+}
+
+struct Client {
+    requests: mpsc::Sender<Request>,
+    query: watch::Sender<Option<QueryRequest>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    /// Waiting for the next attempt, in seconds.
+    Retrying(u64),
+    /// Elephant speaks another protocol version, carries elephant's version.
+    VersionMismatch(i32),
+}
 
-static CONN: Mutex<Option<UnixStream>> = Mutex::new(None);
-static MENUCONN: Mutex<Option<UnixStream>> = Mutex::new(None);
-static BLUETOOTHCONN: Mutex<Option<UnixStream>> = Mutex::new(None);
+impl ConnectionState {
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "connection-connecting",
+            ConnectionState::Connected => "connection-connected",
+            ConnectionState::Retrying(_) => "connection-retrying",
+            ConnectionState::VersionMismatch(_) => "connection-version-mismatch",
+        }
+    }
+}
 
 pub fn input_changed(text: &str) {
     set_current_prefix(String::new());
 
     with_window(|w| {
-        let is_empty = if text.is_empty() {
+        if text.is_empty() {
             w.window.remove_css_class("has-input");
-            true
         } else {
             w.window.add_css_class("has-input");
-            false
-        };
-
-        if is_dmenu() || is_emergency() || is_actions_menu() {
-            if is_empty {
-                set_query("");
-
-                let list_store = &w.items;
-
-                list_store
-                    .iter()
-                    .flatten()
-                    .map(Object::downcast::<QueryResponseObject>)
-                    .filter_map(Result::ok)
-                    .for_each(|i| i.set_dmenu_score(0));
-            } else {
-                set_query(text);
-            }
+        }
 
-            sort_items_fuzzy(text);
+        if is_local() {
+            set_query(text);
+            filter_local_items(text);
         } else if is_connected() {
             if !get_provider().is_empty() {
                 get_provider_state(get_provider());
@@ -76,300 +132,378 @@ pub fn input_changed(text: &str) {
     });
 }
 
-fn sort_items_fuzzy(query: &str) {
-    with_window(|w| {
-        let list_store = &w.items;
-
-        let mut items: Vec<QueryResponseObject> = list_store
-            .iter()
-            .flatten()
-            .map(Object::downcast::<QueryResponseObject>)
-            .filter_map(Result::ok)
-            .collect();
-
-        list_store.remove_all();
-
-        if query.is_empty() {
-            items.sort_by(|a, b| {
-                let score_a = a.response().item.as_ref().map(|i| i.score);
-                let score_b = b.response().item.as_ref().map(|i| i.score);
-                score_b.cmp(&score_a)
-            });
-        } else {
-            let texts = items
-                .iter()
-                .map(QueryResponseObject::response)
-                .map(|response| response.item)
-                .filter_map(MessageField::into_option)
-                .map(|item| item.text);
-
-            let mut matcher = Matcher::new(Config::DEFAULT.match_paths());
-            let pattern = Pattern::parse(query, CaseMatching::Ignore, Normalization::Smart);
-            let matches: Vec<(String, u32)> = pattern.match_list(texts, &mut matcher);
-            let score_map: HashMap<String, u32> = HashMap::from_iter(matches);
-
-            items.sort_by(|a, b| {
-                let ra = a.response();
-                let text_a = ra
-                    .item
-                    .as_ref()
-                    .map(|i| i.text.as_str())
-                    .unwrap_or_default();
-                let rb = b.response();
-                let text_b = rb
-                    .item
-                    .as_ref()
-                    .map(|i| i.text.as_str())
-                    .unwrap_or_default();
-
-                match (score_map.get(text_a), score_map.get(text_b)) {
-                    (Some(aa), Some(bb)) => {
-                        a.set_dmenu_score(*aa);
-                        b.set_dmenu_score(*bb);
-                        bb.cmp(aa)
-                    }
-                    (Some(aa), None) => {
-                        a.set_dmenu_score(*aa);
-                        Ordering::Less
-                    }
-                    (None, Some(bb)) => {
-                        b.set_dmenu_score(*bb);
-                        Ordering::Greater
-                    }
-                    (None, None) => text_a.cmp(text_b),
-                }
-            });
-        }
+/// Start the elephant client. The connection is kept alive in the background
+/// and re-established whenever it drops.
+pub fn init_socket() {
+    let (requests, requests_rx) = mpsc::channel(REQUEST_QUEUE_LEN);
+    let (query, query_rx) = watch::channel(None);
 
-        list_store.extend_from_slice(&items);
-    });
-}
-
-pub fn init_socket() -> Result<(), Box<dyn std::error::Error>> {
-    if is_connecting() {
-        return Ok(());
+    if CLIENT.set(Client { requests, query }).is_ok() {
+        RUNTIME.spawn(run_client(requests_rx, query_rx));
     }
+}
 
-    set_is_connecting(true);
-    println!("connecting to elephant...");
-
-    let mut socket_path = env::var("XDG_RUNTIME_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| env::temp_dir());
-
-    socket_path.push("elephant");
-    socket_path.push("elephant.sock");
-
-    println!("waiting for elephant to start...");
-    wait_for_file(&socket_path.to_string_lossy());
-
+async fn run_client(
+    mut requests: mpsc::Receiver<Request>,
+    mut query: watch::Receiver<Option<QueryRequest>>,
+) {
     let mut handled = false;
+    let mut delay = RETRY_DELAY_MIN;
 
-    let conn = loop {
-        match UnixStream::connect(&socket_path) {
-            Ok(conn) => break conn,
+    loop {
+        update_connection_state(ConnectionState::Connecting);
+
+        let (conn, hello) = match connect_and_greet().await {
+            Ok((conn, None)) => (conn, None),
+            Ok((conn, Some(hello))) if hello.version == PROTOCOL_VERSION => (conn, Some(hello)),
+            Ok((_, Some(hello))) => {
+                eprintln!(
+                    "elephant speaks protocol version {}, walker requires {PROTOCOL_VERSION}",
+                    hello.version
+                );
+
+                update_connection_state(ConnectionState::VersionMismatch(hello.version));
+                RETRY_NOW.notified().await;
+                continue;
+            }
             Err(e) => {
-                if !handled {
+                if !handled && !is_dmenu() {
                     handle_emergency();
-                    handled = true
+                    handled = true;
                 }
 
-                println!("Failed to connect: {e}. Retrying in 1 second...");
-                thread::sleep(Duration::from_secs(1));
-            }
-        }
-    };
-    *CONN.lock().unwrap() = Some(conn);
+                println!(
+                    "Failed to connect: {e}. Retrying in {} seconds...",
+                    delay.as_secs()
+                );
 
-    let menuconn = loop {
-        match UnixStream::connect(&socket_path) {
-            Ok(conn) => break conn,
-            Err(e) => {
-                println!("Failed to connect to menu: {e}. Retrying in 1 second...");
-                thread::sleep(Duration::from_secs(1));
+                wait_for_retry(delay).await;
+                delay = (delay * 2).min(RETRY_DELAY_MAX);
+                continue;
             }
+        };
+
+        handled = false;
+        delay = RETRY_DELAY_MIN;
+
+        let greet = hello.is_some();
+        let features = hello.as_ref().map_or_else(Features::default, |hello| {
+            Features::from_capabilities(&hello.capabilities)
+        });
+
+        if get_config().installed_providers.is_none()
+            && let Some(providers) = provider_names(hello).await
+        {
+            reload_changed_providers(providers);
         }
-    };
 
-    *MENUCONN.lock().unwrap() = Some(menuconn);
+        // requests queued for the previous connection are stale by now
+        while requests.try_recv().is_ok() {}
+        query.mark_unchanged();
+
+        set_is_emergency(false);
+        set_features(features);
+        update_connection_state(ConnectionState::Connected);
+        resubscribe();
 
-    if PROVIDERS.get().unwrap().get("bluetooth").is_some() {
-        let bluetoothconn = loop {
-            match UnixStream::connect(&socket_path) {
-                Ok(conn) => break conn,
-                Err(e) => {
-                    println!("Failed to connect to menu: {e}. Retrying in 1 second...");
-                    thread::sleep(Duration::from_secs(1));
+        glib::idle_add_once(|| {
+            with_window(|w| {
+                if let Some(input) = &w.input {
+                    input.emit_by_name::<()>("changed", &[]);
                 }
-            }
-        };
 
-        *BLUETOOTHCONN.lock().unwrap() = Some(bluetoothconn);
-        subscribe_bluetooth().unwrap();
+                if !is_emergency() && get_error() == "Emergency Mode" {
+                    set_error(String::new());
+                    check_error();
+                }
+
+                if !is_dmenu() {
+                    set_keybind_hint();
+                }
+            });
+        });
+
+        println!("connected.");
+
+        if let Err(e) = serve(conn, features, greet, &mut requests, &mut query).await {
+            eprintln!("elephant connection error: {e}");
+        }
     }
+}
 
-    subscribe_menu().unwrap();
-    start_listening();
+fn update_connection_state(state: ConnectionState) {
+    set_connection_state(state);
+    glib::idle_add_once(move || show_connection_state(state));
+}
 
-    glib::idle_add_once(|| {
-        with_window(|w| {
-            w.elephant_hint.set_visible(false);
-            w.scroll.set_visible(true);
+/// Count down to the next connection attempt, or until `retry_now` is called.
+async fn wait_for_retry(delay: Duration) {
+    for remaining in (1..=delay.as_secs()).rev() {
+        update_connection_state(ConnectionState::Retrying(remaining));
 
-            set_is_connected(true);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            _ = RETRY_NOW.notified() => return,
+        }
+    }
+}
 
-            if let Some(input) = &w.input {
-                input.emit_by_name::<()>("changed", &[]);
+/// Skip the remaining wait before the next connection attempt.
+pub fn retry_now() {
+    if matches!(
+        get_connection_state(),
+        ConnectionState::Retrying(_) | ConnectionState::VersionMismatch(_)
+    ) {
+        RETRY_NOW.notify_one();
+    }
+}
+
+/// Write outgoing requests and hand incoming responses to the UI until the
+/// connection fails. Only the latest pending query is written, older ones are
+/// dropped before they reach elephant. `greet` tells whether elephant knows the
+/// hello exchange, for further connections.
+async fn serve(
+    conn: UnixStream,
+    features: Features,
+    greet: bool,
+    requests: &mut mpsc::Receiver<Request>,
+    query: &mut watch::Receiver<Option<QueryRequest>>,
+) -> Result<(), FrameError> {
+    let debounce = Duration::from_millis(get_config().query_debounce);
+    let (reader, mut writer) = conn.into_split();
+
+    // ids start over with every connection, results of the previous one are gone
+    LATEST_QID.store(0, AtomicOrdering::Relaxed);
+    PENDING_CHANGES.lock().unwrap().clear();
+
+    let mut listener = tokio::spawn(listen_loop(reader));
+    // dropping the set closes the subscription connections along with this one
+    let mut subscriptions = JoinSet::new();
+
+    let result = loop {
+        let req = tokio::select! {
+            res = &mut listener => {
+                break res.unwrap_or_else(|e| Err(FrameError::Io(io::Error::other(e))));
+            }
+            Some(res) = subscriptions.join_next() => {
+                break res.unwrap_or_else(|e| Err(FrameError::Io(io::Error::other(e))));
             }
+            Some(req) = requests.recv() => req,
+            Ok(()) = query.changed() => {
+                // let further input replace the query before it is written
+                if !debounce.is_zero() {
+                    tokio::time::sleep(debounce).await;
+                }
 
-            if !is_emergency() && get_error() == "Emergency Mode" {
-                set_error(String::new());
-                check_error();
+                match query.borrow_and_update().clone() {
+                    Some(mut req) => {
+                        req.qid = LATEST_QID.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+                        Request::Query(req)
+                    }
+                    None => continue,
+                }
             }
+        };
 
-            if !is_dmenu() {
-                set_keybind_hint();
+        let req = match req {
+            Request::Subscribe(req) if !features.subscription_ids => {
+                subscriptions.spawn(serve_subscription(req, greet));
+                continue;
             }
-        });
-    });
+            req => req,
+        };
 
-    set_is_connecting(false);
-    set_is_emergency(false);
+        if let Err(e) = write_request(&mut writer, &req).await {
+            break Err(e);
+        }
+    };
 
-    println!("connected.");
+    listener.abort();
 
-    Ok(())
+    result
 }
 
-fn start_listening() {
-    thread::spawn(|| {
-        if let Err(e) = listen_loop() {
-            eprintln!("Listen loop error: {e}");
-            handle_disconnect();
+/// Serve a subscription on its own connection, for elephant versions without
+/// [`Features::subscription_ids`]. Only returns if the connection fails.
+async fn serve_subscription(req: SubscribeRequest, greet: bool) -> Result<(), FrameError> {
+    let sid = req.sid;
+
+    let mut conn = if greet {
+        connect_and_greet()
+            .await
+            .map_err(|e| FrameError::Io(io::Error::other(e)))?
+            .0
+    } else {
+        connect().await?
+    };
+
+    write_request(&mut conn, &Request::Subscribe(req)).await?;
+
+    let mut reader = BufReader::new(conn);
+
+    loop {
+        match read_subscription_update(&mut reader).await {
+            Ok(mut resp) => {
+                resp.sid = sid;
+                dispatch(resp);
+            }
+            Err(e) if e.is_recoverable() => eprintln!("subscription {sid}: {e}"),
+            Err(e) => return Err(e),
         }
-    });
+    }
+}
+
+/// Ask elephant for its installed providers. `None` if it can't be reached.
+/// Blocks, use `refresh_providers` on the main thread.
+pub fn fetch_providers() -> Option<Vec<String>> {
+    RUNTIME.block_on(discover_providers())
+}
+
+/// Ask elephant for its installed providers in the background and reload the
+/// providers if they changed. Does nothing if the config lists them.
+pub fn refresh_providers() {
+    if get_config().installed_providers.is_some() {
+        return;
+    }
 
-    thread::spawn(|| {
-        if let Err(e) = listen_menus_loop() {
-            eprintln!("Listen menu_loop error: {e}");
-            handle_disconnect();
+    RUNTIME.spawn(async {
+        if let Some(providers) = discover_providers().await {
+            reload_changed_providers(providers);
         }
     });
+}
 
-    if PROVIDERS.get().unwrap().get("bluetooth").is_some() {
-        thread::spawn(|| {
-            if let Err(e) = listen_bluetooth_loop() {
-                eprintln!("Listen bluetooth_loop error: {e}");
-                handle_disconnect();
-            }
-        });
+async fn discover_providers() -> Option<Vec<String>> {
+    match connect_and_greet().await {
+        Ok((_, hello)) => provider_names(hello).await,
+        Err(e) => {
+            eprintln!("couldn't get providers from elephant: {e}");
+            None
+        }
     }
 }
 
-fn listen_bluetooth_loop() -> Result<(), Box<dyn std::error::Error>> {
-    let mut conn_guard = BLUETOOTHCONN.lock().unwrap();
-    let conn = conn_guard.as_mut().ok_or("Connection not initialized")?;
+/// The providers from the hello answer, or from the `elephant` command for
+/// elephant versions without the exchange.
+async fn provider_names(hello: Option<HelloResponse>) -> Option<Vec<String>> {
+    match hello {
+        Some(hello) => Some(hello.providers),
+        None => tokio::task::spawn_blocking(list_providers)
+            .await
+            .ok()
+            .flatten(),
+    }
+}
 
-    let mut conn_clone = conn.try_clone()?;
-    drop(conn_guard);
+fn reload_changed_providers(providers: Vec<String>) {
+    glib::idle_add_once(move || {
+        if has_providers(&providers) {
+            return;
+        }
 
-    let mut reader = BufReader::new(&mut conn_clone);
+        if let Err(e) = crate::reload_providers(providers) {
+            eprintln!("{e}");
+        }
+    });
+}
 
-    loop {
-        let mut header = [0u8; 5];
-        reader.read_exact(&mut header)?;
-
-        match header[0] {
-            0 => {
-                let length = u32::from_be_bytes(header[1..].try_into().unwrap());
-
-                let mut payload = vec![0u8; length as usize];
-                reader.read_exact(&mut payload)?;
-
-                let mut resp = SubscribeResponse::new();
-                resp.merge_from_bytes(&payload)?;
-
-                glib::idle_add_once(move || {
-                    with_window(|w| {
-                        if let Some(p) = &w.placeholder {
-                            match resp.value.as_str() {
-                                "bluetooth:remove" => p.set_text("Removing..."),
-                                "bluetooth:connect" => p.set_text("Connecting..."),
-                                "bluetooth:disconnect" => p.set_text("Disconnecting..."),
-                                "bluetooth:trust" => p.set_text("Trusting..."),
-                                "bluetooth:untrust" => p.set_text("Un-Trusting..."),
-                                "bluetooth:pair" => p.set_text("Pairing..."),
-                                "bluetooth:find" => p.set_text("Scanning..."),
-                                _ => (),
-                            }
+/// Ask the `elephant` command for the installed providers, for elephant
+/// versions without the hello exchange.
+fn list_providers() -> Option<Vec<String>> {
+    let output = match Command::new("elephant").arg("listproviders").output() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Error running 'elephant' command: {e}. Make sure it is installed.");
+            return None;
+        }
+    };
 
-                            p.set_visible(true);
-                            w.scroll.set_visible(false);
-                        }
-                    });
-                });
-            }
-            _ => continue,
+    match String::from_utf8(output.stdout) {
+        Ok(stdout) => Some(
+            stdout
+                .lines()
+                .filter_map(|line| line.split_once(';').map(|(_, value)| value.to_string()))
+                .collect(),
+        ),
+        Err(e) => {
+            eprintln!("Error parsing elephant output as UTF-8: {e}");
+            None
         }
     }
 }
 
-fn listen_menus_loop() -> Result<(), Box<dyn std::error::Error>> {
-    let mut conn_guard = MENUCONN.lock().unwrap();
-    let conn = conn_guard.as_mut().ok_or("Connection not initialized")?;
+/// Connect and say hello. Elephant versions without the hello exchange get a
+/// fresh connection in the original wire format and `None` for an answer. The
+/// endpoint is remembered, so further connections to it don't say hello.
+async fn connect_and_greet()
+-> Result<(UnixStream, Option<HelloResponse>), Box<dyn std::error::Error + Send + Sync>> {
+    let endpoint = get_config().elephant_socket.clone();
 
-    let mut conn_clone = conn.try_clone()?;
-    drop(conn_guard);
+    if LEGACY_ENDPOINT.lock().unwrap().as_ref() == Some(&endpoint) {
+        return Ok((connect().await?, None));
+    }
 
-    let mut reader = BufReader::new(&mut conn_clone);
+    let mut conn = connect().await?;
 
-    loop {
-        let mut header = [0u8; 5];
-        reader.read_exact(&mut header)?;
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, hello(&mut conn)).await {
+        Ok(Ok(Some(hello))) => return Ok((conn, Some(hello))),
+        Ok(Ok(None)) | Err(_) => {}
+        Ok(Err(e)) => return Err(e.into()),
+    }
 
-        match header[0] {
-            0 => {
-                let length = u32::from_be_bytes(header[1..].try_into().unwrap());
+    println!("elephant doesn't answer the hello request, using the original protocol.");
+    *LEGACY_ENDPOINT.lock().unwrap() = Some(endpoint);
 
-                let mut payload = vec![0u8; length as usize];
-                reader.read_exact(&mut payload)?;
+    Ok((connect().await?, None))
+}
 
-                let mut resp = SubscribeResponse::new();
-                resp.merge_from_bytes(&payload)?;
+/// Connect to the configured endpoint. A leading `@` names an abstract socket.
+async fn connect() -> io::Result<UnixStream> {
+    let endpoint = &get_config().elephant_socket;
 
-                glib::idle_add_once(move || {
-                    set_global_provider_actions(None);
-                    set_provider(resp.value);
+    if let Some(name) = endpoint.strip_prefix('@') {
+        let addr = SocketAddr::from_abstract_name(name)?;
+        let conn = StdUnixStream::connect_addr(&addr)?;
+        conn.set_nonblocking(true)?;
 
-                    with_window(|w| {
-                        set_input_text("");
-                        w.window.present();
-                    });
+        return UnixStream::from_std(conn);
+    }
 
-                    set_is_visible(true);
-                });
-            }
-            _ => continue,
-        }
+    if !endpoint.is_empty() {
+        return UnixStream::connect(endpoint).await;
     }
+
+    UnixStream::connect(default_socket_path()).await
 }
 
-fn listen_loop() -> Result<(), Box<dyn std::error::Error>> {
-    let mut conn_guard = CONN.lock().unwrap();
-    let conn = conn_guard.as_mut().ok_or("Connection not initialized")?;
+fn default_socket_path() -> PathBuf {
+    let mut socket_path = env::var("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| env::temp_dir());
 
-    let mut conn_clone = conn.try_clone()?;
-    drop(conn_guard);
+    socket_path.push("elephant");
+    socket_path.push("elephant.sock");
+
+    socket_path
+}
 
-    let mut reader = BufReader::new(&mut conn_clone);
+async fn listen_loop(conn: OwnedReadHalf) -> Result<(), FrameError> {
+    let mut reader = BufReader::new(conn);
 
     loop {
-        let mut header = [0u8; 5];
-        reader.read_exact(&mut header)?;
+        let resp = match read_response(&mut reader).await {
+            Ok(resp) => resp,
+            Err(e) if e.is_recoverable() => {
+                eprintln!("query response: {e}");
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
 
-        match header[0] {
-            255 => {
+        match resp {
+            Response::Done => {
                 glib::idle_add_once(|| {
+                    flush_items();
                     check_error();
 
                     handle_changed_items();
@@ -377,23 +511,12 @@ fn listen_loop() -> Result<(), Box<dyn std::error::Error>> {
                     crate::ui::window::handle_preview();
                 });
             }
-            254 => {
-                glib::idle_add_once(clear_items);
-            }
-            253 => {}
-            230 => {}
-            3 => {
-                let length = u32::from_be_bytes(header[1..].try_into().unwrap());
-
-                let mut payload = vec![0u8; length as usize];
-                reader.read_exact(&mut payload)?;
-
-                let mut resp = ProviderStateResponse::new();
-                resp.merge_from_bytes(&payload)?;
-
+            Response::Clear => queue_change(PendingChange::Clear),
+            Response::HealthCheck | Response::NoResults | Response::Hello(_) => {}
+            Response::ProviderState(resp) => {
                 set_global_provider_state(resp);
             }
-            2 => {
+            Response::ActionDone => {
                 glib::idle_add_once(move || match get_async_after() {
                     Some(AfterAction::AsyncReload) => {
                         with_window(|w| {
@@ -425,36 +548,23 @@ fn listen_loop() -> Result<(), Box<dyn std::error::Error>> {
                     _ => (),
                 });
             }
-            _ => {
-                let length = u32::from_be_bytes(header[1..].try_into().unwrap());
-
-                let mut payload = vec![0u8; length as usize];
-                reader.read_exact(&mut payload)?;
-
-                let mut resp = QueryResponse::new();
-                resp.merge_from_bytes(&payload)?;
-
-                glib::idle_add_once(move || handle_response(resp, header[0]));
-            }
+            Response::Item(resp) => queue_change(PendingChange::Item(resp)),
+            Response::ItemUpdate(resp) => queue_change(PendingChange::Update(resp)),
+            Response::Subscription(resp) => dispatch(resp),
         };
     }
 }
 
-fn handle_response(resp: QueryResponse, header_type: u8) {
-    let function = match header_type {
-        1 => update_existing_item,
-        _ => add_new_item,
-    };
-
-    function(resp)
-}
-
 fn clear_items() {
     with_window(|w| w.items.remove_all());
     crate::preview::clear_all_caches();
 }
 
 fn update_existing_item(resp: QueryResponse) {
+    if is_stale(&resp) {
+        return;
+    }
+
     with_window(|w| {
         let items = &w.items;
         let n_items = items.n_items();
@@ -486,48 +596,113 @@ fn update_existing_item(resp: QueryResponse) {
     });
 }
 
-fn add_new_item(resp: QueryResponse) {
+fn queue_change(change: PendingChange) {
+    if let PendingChange::Item(resp) | PendingChange::Update(resp) = &change
+        && is_stale(resp)
+    {
+        return;
+    }
+
+    PENDING_CHANGES.lock().unwrap().push(change);
+
+    if !FLUSH_SCHEDULED.swap(true, AtomicOrdering::AcqRel) {
+        glib::timeout_add_once(BATCH_INTERVAL, flush_items);
+    }
+}
+
+/// Apply all pending changes in order. Consecutive items are inserted with a
+/// single splice.
+fn flush_items() {
+    FLUSH_SCHEDULED.store(false, AtomicOrdering::Release);
+
+    let pending = std::mem::take(&mut *PENDING_CHANGES.lock().unwrap());
+    let mut batch: Vec<QueryResponse> = Vec::new();
+
+    for change in pending {
+        match change {
+            PendingChange::Item(resp) => batch.push(resp),
+            PendingChange::Update(resp) => {
+                insert_items(std::mem::take(&mut batch));
+                update_existing_item(resp);
+            }
+            PendingChange::Clear => {
+                batch.clear();
+                clear_items();
+            }
+        }
+    }
+
+    insert_items(batch);
+}
+
+/// Items of another query replace the list, items of the shown query are
+/// appended.
+fn insert_items(mut batch: Vec<QueryResponse>) {
+    batch.retain(|resp| !is_stale(resp));
+
+    let Some(qid) = batch.iter().map(|resp| resp.qid).max() else {
+        return;
+    };
+
+    let objs: Vec<QueryResponseObject> = batch.into_iter().map(QueryResponseObject::new).collect();
+
     with_window(|w| {
         let items = &w.items;
         let n_items = items.n_items();
 
-        if let Some(n_items) = n_items.checked_sub(1)
-            && let Some(last_obj) = items
-                .item(n_items)
-                .and_downcast::<crate::QueryResponseObject>()
-        {
-            let last_resp = last_obj.response();
+        let shown_qid = n_items
+            .checked_sub(1)
+            .and_then(|last| items.item(last).and_downcast::<QueryResponseObject>())
+            .map(|obj| obj.response().qid);
 
-            if resp.qid > last_resp.qid {
-                items.remove_all();
-            }
+        match shown_qid {
+            Some(shown) if shown == qid => items.splice(n_items, 0, &objs),
+            _ => items.splice(0, n_items, &objs),
         }
-
-        items.append(&crate::QueryResponseObject::new(resp));
     });
 }
 
 fn query(text: &str) {
+    let (req, prefix) = build_query_request(text, &get_provider(), &get_current_set());
+
+    match prefix {
+        Some(prefix) => {
+            set_current_prefix(prefix.prefix);
+            set_prefix_provider(prefix.provider);
+        }
+        None => set_prefix_provider(String::new()),
+    }
+
+    send_query(req);
+}
+
+/// Resolve `text` into a query request for the given provider or set. Also
+/// returns the configured prefix that selected the provider, if any.
+fn build_query_request(text: &str, provider: &str, set: &str) -> (QueryRequest, Option<Prefix>) {
     let mut query_text = text.to_string();
     let mut exact = false;
     let cfg = get_config();
-    let mut provider = get_provider();
-    let providers = PROVIDERS.get().unwrap();
+    let mut provider = provider.to_string();
+    let providers = get_providers();
 
-    if get_provider().is_empty()
-        && let Some(prefix) = cfg.providers.prefixes.iter().find(|prefix| {
-            text.starts_with(&prefix.prefix) && providers.contains_key(&prefix.provider)
-        })
-    {
+    let prefix = if provider.is_empty() {
+        cfg.providers
+            .prefixes
+            .iter()
+            .find(|prefix| {
+                text.starts_with(&prefix.prefix) && providers.contains_key(&prefix.provider)
+            })
+            .cloned()
+    } else {
+        None
+    };
+
+    if let Some(prefix) = &prefix {
         provider = prefix.provider.clone();
         query_text = text
             .strip_prefix(&prefix.prefix)
             .unwrap_or(text)
             .to_string();
-        set_current_prefix(prefix.prefix.clone());
-        set_prefix_provider(provider.clone());
-    } else {
-        set_prefix_provider(String::new());
     }
 
     let delimiter = &cfg.global_argument_delimiter;
@@ -550,7 +725,7 @@ fn query(text: &str) {
     }
 
     if req.providers.is_empty() {
-        if get_current_set().is_empty() {
+        if set.is_empty() {
             if text.is_empty() {
                 req.providers = cfg.providers.empty.clone();
             } else {
@@ -560,7 +735,7 @@ fn query(text: &str) {
             let set = cfg
                 .providers
                 .sets
-                .get(&get_current_set())
+                .get(set)
                 .expect("can't find specified set");
             if text.is_empty() {
                 req.providers = set.empty.clone();
@@ -584,29 +759,16 @@ fn query(text: &str) {
         req.maxresults = cfg.providers.max_results;
     }
 
-    let mut buffer = vec![0, 0];
-    let length = req.compute_size() as u32;
-    buffer.extend_from_slice(&length.to_be_bytes());
-    req.write_to_vec(&mut buffer).unwrap();
-
-    if let Some(conn) = CONN.lock().unwrap().as_mut() {
-        match conn.write_all(&buffer) {
-            Err(e) => {
-                eprintln!("send query socket error: {e}");
-                handle_disconnect();
-            }
-            _ => (),
-        }
-    }
+    (req, prefix)
 }
 
 fn handle_emergency() {
-    if let Some(e) = &get_config().emergencies
+    if let Some(e) = get_config().emergencies.clone()
         && !is_emergency()
     {
         set_is_emergency(true);
 
-        glib::idle_add_once(|| {
+        glib::idle_add_once(move || {
             with_window(|w| {
                 w.items.remove_all();
 
@@ -632,78 +794,65 @@ fn handle_emergency() {
                 set_keybind_hint();
             });
         });
-    } else {
-        glib::idle_add_once(|| {
-            with_window(|w| {
-                w.elephant_hint.set_visible(true);
-                w.scroll.set_visible(false);
-
-                if let Some(p) = &w.preview_container {
-                    p.set_visible(false);
-                }
-
-                w.keybinds.set_visible(false);
-            });
-        });
     }
 }
 
-fn handle_disconnect() {
-    set_is_connected(false);
-
-    thread::spawn(|| {
-        while let Err(err) = init_socket() {
-            println!("{err}");
-        }
-    });
-}
-
 pub fn set_state(provider: &str, action: &str) {
     let mut req = ActivateRequest::new();
     req.action = action.to_string();
     req.provider = provider.to_string();
 
-    let mut buffer = vec![1, 0];
-    let length = req.compute_size() as u32;
-    buffer.extend_from_slice(&length.to_be_bytes());
-    req.write_to_vec(&mut buffer).unwrap();
-
-    let mut conn_guard = CONN.lock().unwrap();
-
-    if let Some(conn) = conn_guard.as_mut() {
-        match conn.write_all(&buffer) {
-            Err(e) => {
-                eprintln!("send clipboard disable images only socket error: {e}");
-                handle_disconnect();
-            }
-            _ => (),
-        }
-    }
+    send_request(Request::Activate(req), "set state");
 }
 
 pub fn get_provider_state(provider: String) {
     let mut req = ProviderStateRequest::new();
     req.provider = provider;
 
-    let mut buffer = vec![4, 0];
-    let length = req.compute_size() as u32;
-    buffer.extend_from_slice(&length.to_be_bytes());
-    req.write_to_vec(&mut buffer).unwrap();
+    send_request(Request::ProviderState(req), "providerstate request");
+}
 
-    let mut conn_guard = CONN.lock().unwrap();
+pub fn activate(item_option: Option<QueryResponse>, provider: &str, query: &str, action: &Action) {
+    let single = !get_provider().is_empty() || !get_prefix_provider().is_empty();
 
-    if let Some(conn) = conn_guard.as_mut() {
-        match conn.write_all(&buffer) {
-            Err(e) => {
-                eprintln!("send providerstate request socket error: {e}");
-                handle_disconnect();
+    let req = match item_option {
+        Some(item) => match provider {
+            "dmenu" => {
+                print_dmenu_result(accept_result(dmenu_result(Some(&item.item)), None));
+                return;
             }
-            _ => (),
+            "providerlist" => {
+                set_provider(item.item.identifier.to_string());
+                set_current_prefix(String::new());
+                return;
+            }
+            _ => activate_request(&item.item, query, &action.action, single),
+        },
+        None => {
+            let mut req = ActivateRequest::new();
+            req.action = action.action.to_string();
+            req.provider = provider.to_string();
+            req.single = single;
+
+            if provider.starts_with("menus:") {
+                req.identifier = provider.to_string();
+            }
+
+            req
         }
-    }
+    };
+
+    send_request(Request::Activate(req), "activate");
 }
 
-pub fn activate(item_option: Option<QueryResponse>, provider: &str, query: &str, action: &Action) {
+/// Build the request to run `action` on `item`. Arguments after the argument
+/// delimiter and a leading provider prefix are split off `query`.
+pub fn activate_request(
+    item: &query_response::Item,
+    query: &str,
+    action: &str,
+    single: bool,
+) -> ActivateRequest {
     let cfg = get_config();
 
     let mut query = query;
@@ -712,149 +861,185 @@ pub fn activate(item_option: Option<QueryResponse>, provider: &str, query: &str,
     }
 
     let mut req = ActivateRequest::new();
-    req.action = action.action.to_string();
-    req.provider = provider.to_string();
-    req.single = false;
-
-    if !get_provider().is_empty() || !get_prefix_provider().is_empty() {
-        req.single = true;
-    }
-
-    if let Some(item) = item_option {
-        match provider {
-            "dmenu" => {
-                let mut res = item.item.text.clone();
-
-                if is_index() {
-                    res = format!("{}", 1000000 - item.item.score);
-                }
+    req.action = action.to_string();
+    req.provider = item.provider.clone();
+    req.identifier = item.identifier.clone();
+    req.single = single;
 
-                if is_service() {
-                    send_message(res);
-                } else {
-                    println!("{}", res);
-                }
+    let delimiter = if let Some(d) = cfg.providers.argument_delimiter.get(&item.provider) {
+        d
+    } else {
+        &cfg.global_argument_delimiter
+    };
 
-                return;
-            }
-            "providerlist" => {
-                set_provider(item.item.identifier.to_string());
-                set_current_prefix(String::new());
-                return;
-            }
-            _ => {
-                req.query = query.to_string();
-
-                let delimiter =
-                    if let Some(d) = cfg.providers.argument_delimiter.get(&item.item.provider) {
-                        d
-                    } else {
-                        &cfg.global_argument_delimiter
-                    };
-
-                match query.split_once(delimiter) {
-                    Some(res) => {
-                        req.query = res.0.to_string();
-                        req.arguments = res.1.to_string();
-                    }
-                    None => {
-                        req.query = query.to_string();
-                    }
-                }
-                req.provider = item.item.provider.clone();
-                req.identifier = item.item.identifier.clone();
-            }
+    match query.split_once(delimiter) {
+        Some(res) => {
+            req.query = res.0.to_string();
+            req.arguments = res.1.to_string();
+        }
+        None => {
+            req.query = query.to_string();
         }
-    } else if provider.starts_with("menus:") {
-        req.identifier = provider.to_string();
     }
 
     if let Some(prefix) = cfg
         .providers
         .prefixes
         .iter()
-        .find(|prefix| provider == prefix.provider && query.starts_with(&prefix.prefix))
+        .find(|prefix| item.provider == prefix.provider && query.starts_with(&prefix.prefix))
     {
         if let Some(after) = req.query.to_string().strip_prefix(&prefix.prefix) {
             req.query = after.to_string();
         }
     }
 
-    let mut buffer = vec![1, 0];
-    let length = req.compute_size() as u32;
-    buffer.extend_from_slice(&length.to_be_bytes());
-    req.write_to_vec(&mut buffer).unwrap();
+    req
+}
 
-    let mut conn_guard = CONN.lock().unwrap();
+/// Run `text` as a single query on its own connection and collect the results
+/// until elephant marks them as done. Doesn't touch the UI or the shared client.
+pub fn query_once(
+    text: &str,
+    provider: &str,
+    set: &str,
+) -> Result<Vec<QueryResponse>, Box<dyn std::error::Error + Send + Sync>> {
+    let (req, _) = build_query_request(text, provider, set);
+
+    RUNTIME.block_on(async {
+        let mut conn = connect_once().await?;
+        write_request(&mut conn, &Request::Query(req)).await?;
+
+        let mut results: Vec<QueryResponse> = Vec::new();
+
+        loop {
+            let resp = match read_response(&mut conn).await {
+                Ok(resp) => resp,
+                Err(e) if e.is_recoverable() => {
+                    eprintln!("query response: {e}");
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
-    if let Some(conn) = conn_guard.as_mut() {
-        match conn.write_all(&buffer) {
-            Err(e) => {
-                eprintln!("send activate socket error: {e}");
-                handle_disconnect();
+            match resp {
+                Response::Item(resp) => results.push(resp),
+                Response::ItemUpdate(resp) => {
+                    let identifier = resp.item.identifier.clone();
+
+                    results.retain(|r| r.item.identifier != identifier);
+
+                    if resp.item.text != "%DELETE%" {
+                        results.push(resp);
+                    }
+                }
+                Response::Clear => results.clear(),
+                Response::Done => return Ok(results),
+                _ => (),
             }
-            _ => (),
         }
-    }
+    })
 }
 
-fn subscribe_menu() -> Result<(), Box<dyn std::error::Error>> {
-    let mut req = SubscribeRequest::new();
-    req.provider = "menus".to_string();
+/// Send a single activation on its own connection.
+pub fn activate_once(req: ActivateRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    RUNTIME.block_on(async {
+        let mut conn = connect_once().await?;
+        write_request(&mut conn, &Request::Activate(req)).await?;
+        conn.shutdown().await?;
 
-    let mut buffer = vec![2, 0];
-    let length = req.compute_size() as u32;
-    buffer.extend_from_slice(&length.to_be_bytes());
-    req.write_to_vec(&mut buffer).unwrap();
+        Ok(())
+    })
+}
 
-    {
-        let mut conn_guard = MENUCONN.lock().unwrap();
+async fn connect_once() -> Result<UnixStream, Box<dyn std::error::Error + Send + Sync>> {
+    let (conn, hello) = connect_and_greet().await?;
 
-        if let Some(conn) = conn_guard.as_mut() {
-            conn.write_all(&buffer)?;
-        } else {
-            return Err("Connection not available".into());
-        }
+    if let Some(hello) = hello
+        && hello.version != PROTOCOL_VERSION
+    {
+        return Err(format!(
+            "elephant speaks protocol version {}, walker requires {PROTOCOL_VERSION}",
+            hello.version
+        )
+        .into());
     }
 
-    Ok(())
+    Ok(conn)
 }
 
-fn subscribe_bluetooth() -> Result<(), Box<dyn std::error::Error>> {
-    let mut req = SubscribeRequest::new();
-    req.provider = "bluetooth".to_string();
+/// Values a dmenu accept returns: all marked items in input order with `--multi`,
+/// otherwise the accepted item.
+pub fn dmenu_result(accepted: Option<&query_response::Item>) -> Vec<String> {
+    let marked = if is_multi() {
+        get_marked_items()
+    } else {
+        Vec::new()
+    };
 
-    let mut buffer = vec![2, 0];
-    let length = req.compute_size() as u32;
-    buffer.extend_from_slice(&length.to_be_bytes());
-    req.write_to_vec(&mut buffer).unwrap();
+    if !marked.is_empty() {
+        return marked.iter().map(dmenu_value).collect();
+    }
 
-    {
-        let mut conn_guard = BLUETOOTHCONN.lock().unwrap();
+    accepted.map(dmenu_value).into_iter().collect()
+}
 
-        if let Some(conn) = conn_guard.as_mut() {
-            conn.write_all(&buffer)?;
-        } else {
-            return Err("Connection not available".into());
-        }
+/// The item's line, or its input index with `--index`. Only the fields picked
+/// by `--accept-nth` are returned.
+fn dmenu_value(item: &query_response::Item) -> String {
+    if is_index() {
+        return format!("{}", 1000000 - item.score);
     }
 
-    Ok(())
-}
+    // the identifier holds the full line, the text only the displayed fields
+    let line = if item.identifier.is_empty() {
+        &item.text
+    } else {
+        &item.identifier
+    };
 
-fn wait_for_file(path: &str) {
-    let mut handled = false;
+    match get_dmenu_accept_nth() {
+        Some(fields) => fields.apply(line, get_dmenu_delimiter().as_deref()),
+        None => line.clone(),
+    }
+}
 
-    while !Path::new(path).exists() {
-        thread::sleep(Duration::from_millis(10));
+/// Hand the dmenu result to the waiting command line, or print it.
+pub fn print_dmenu_result(result: DmenuResult) {
+    if is_service() {
+        send_message(result);
+    } else {
+        result.lines.iter().for_each(|l| println!("{l}"));
 
-        if is_dmenu() {
-            handled = false
+        if result.exit_code != 0 {
+            std::process::exit(result.exit_code);
         }
+    }
+}
 
-        if !handled && !is_dmenu() {
-            handle_emergency();
-            handled = true
-        }
+fn send_request(req: Request, context: &str) {
+    if !is_connected() {
+        return;
+    }
+
+    if let Some(client) = CLIENT.get()
+        && let Err(e) = client.requests.try_send(req)
+    {
+        eprintln!("send {context} failed: {e}");
+    }
+}
+
+/// Queue a query, replacing one that hasn't been written yet. It gets its id
+/// when it's written.
+fn send_query(req: QueryRequest) {
+    if let Some(client) = CLIENT.get() {
+        client.query.send_replace(Some(req));
     }
 }
+
+fn is_stale(resp: &QueryResponse) -> bool {
+    resp.qid < LATEST_QID.load(AtomicOrdering::Relaxed)
+}
+
+pub fn send_subscription(req: SubscribeRequest) {
+    send_request(Request::Subscribe(req), "subscribe");
+}
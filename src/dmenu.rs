@@ -0,0 +1,527 @@
+//! Turning dmenu input lines into items.
+//!
+//! Plain lines support field selection like fzf's `--with-nth` and `--accept-nth`.
+//! Lines are split on `--delimiter`, or on whitespace if none is given. Fields
+//! are 1-based, negative indices count from the end, f.e. `1`, `-1`, `2..`,
+//! `..3` or `1..3`. Several selections are separated by commas.
+//!
+//! With `--json` every line is an object like
+//! `{"text":"Firefox","icon":"firefox","value":"firefox.desktop"}`.
+//!
+//! `--preview` commands get `{}` replaced by the line and `{1}`, `{2..}` etc. by
+//! its fields, each shell-quoted.
+//!
+//! Besides Return, keys given with `--expect` accept the selection and are
+//! printed as the first output line, keys given with `--bind key:exitcode`
+//! accept it with that exit code.
+
+use std::fmt;
+
+use gtk4::gdk::{Key, ModifierType};
+use serde::Deserialize;
+
+use crate::keybinds::parse_key;
+use crate::protos::generated_proto::query::query_response::Item;
+use crate::state::get_dmenu_accept_keys;
+
+/// What a dmenu call hands back to the command line. No lines means the call
+/// was cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct DmenuResult {
+    pub lines: Vec<String>,
+    pub exit_code: i32,
+}
+
+/// A key that accepts the selection, from `--expect` or `--bind`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptKey {
+    /// The key as given on the command line.
+    pub name: String,
+    pub key: Key,
+    pub modifier: ModifierType,
+    /// Set for `--bind`, `--expect` keys print their name instead.
+    pub exit_code: Option<i32>,
+}
+
+/// Parse `--expect ctrl-d,alt-Return`.
+pub fn parse_expect(value: &str) -> Result<Vec<AcceptKey>, String> {
+    value
+        .split(',')
+        .map(|name| accept_key(name, None))
+        .collect()
+}
+
+/// Parse `--bind ctrl-d:10,ctrl-e:11`.
+pub fn parse_accept_binds(value: &str) -> Result<Vec<AcceptKey>, String> {
+    value
+        .split(',')
+        .map(|bind| {
+            let (name, code) = bind
+                .rsplit_once(':')
+                .ok_or_else(|| format!("expected <key>:<exitcode>, got '{bind}'"))?;
+
+            let code = code
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| format!("invalid exit code in '{bind}'"))?;
+
+            accept_key(name, Some(code))
+        })
+        .collect()
+}
+
+fn accept_key(name: &str, exit_code: Option<i32>) -> Result<AcceptKey, String> {
+    let name = name.trim();
+    let (key, modifier) = parse_key(name)?;
+
+    Ok(AcceptKey {
+        name: name.to_string(),
+        key,
+        modifier,
+        exit_code,
+    })
+}
+
+/// The accept key matching a key press, if any.
+pub fn find_accept_key(key: Key, modifier: ModifierType) -> Option<AcceptKey> {
+    get_dmenu_accept_keys()
+        .into_iter()
+        .find(|k| k.key.to_lower() == key.to_lower() && k.modifier == modifier)
+}
+
+/// Build the result for the accepted `values`. With `--expect`, the first line
+/// names the key that accepted, or is empty for Return.
+pub fn accept_result(values: Vec<String>, accepted_by: Option<&AcceptKey>) -> DmenuResult {
+    result_for(&get_dmenu_accept_keys(), values, accepted_by)
+}
+
+/// `accept_result` for the given accept keys.
+fn result_for(
+    keys: &[AcceptKey],
+    values: Vec<String>,
+    accepted_by: Option<&AcceptKey>,
+) -> DmenuResult {
+    if values.is_empty() {
+        return DmenuResult::default();
+    }
+
+    let mut lines = Vec::new();
+
+    if keys.iter().any(|k| k.exit_code.is_none()) {
+        lines.push(
+            accepted_by
+                .filter(|k| k.exit_code.is_none())
+                .map(|k| k.name.clone())
+                .unwrap_or_default(),
+        );
+    }
+
+    lines.extend(values);
+
+    DmenuResult {
+        lines,
+        exit_code: accepted_by.and_then(|k| k.exit_code).unwrap_or(0),
+    }
+}
+
+/// How stdin lines are turned into items.
+#[derive(Debug, Clone, Default)]
+pub struct LineFormat {
+    pub json: bool,
+    pub delimiter: Option<String>,
+    pub with_nth: Option<Fields>,
+    pub preview: Option<String>,
+}
+
+/// A `--json` input line. `value` is printed on accept and defaults to `text`.
+#[derive(Debug, Deserialize)]
+struct JsonLine {
+    text: String,
+    #[serde(default)]
+    subtext: String,
+    #[serde(default)]
+    icon: String,
+    #[serde(default)]
+    preview: String,
+    #[serde(default)]
+    preview_type: String,
+    #[serde(default)]
+    state: Vec<String>,
+    value: Option<String>,
+}
+
+impl LineFormat {
+    /// Build the item for `line`. The identifier holds the value to print, the
+    /// text what is shown and matched on.
+    pub fn item(&self, line: &str) -> Result<Item, serde_json::Error> {
+        let mut item = Item::new();
+
+        if self.json {
+            let line: JsonLine = serde_json::from_str(line)?;
+
+            item.identifier = line.value.unwrap_or_else(|| line.text.clone());
+            item.text = line.text;
+            item.subtext = line.subtext;
+            item.icon = line.icon;
+            item.preview = line.preview;
+            item.preview_type = line.preview_type;
+            item.state = line.state;
+        } else {
+            item.identifier = line.to_string();
+            item.text = match &self.with_nth {
+                Some(fields) => fields.apply(line, self.delimiter.as_deref()),
+                None => line.to_string(),
+            };
+        }
+
+        if let Some(template) = &self.preview
+            && item.preview.is_empty()
+        {
+            item.preview = self.preview_command(template, &item.identifier);
+            item.preview_type = "command".to_string();
+        }
+
+        Ok(item)
+    }
+
+    /// Replace every `{}` or `{fields}` placeholder in `template` by the
+    /// shell-quoted line or fields. Anything else in braces is kept as is.
+    fn preview_command(&self, template: &str, line: &str) -> String {
+        let mut result = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+
+            let placeholder = &rest[1..end];
+
+            let value = if placeholder.is_empty() {
+                Some(line.to_string())
+            } else {
+                Fields::parse(placeholder)
+                    .ok()
+                    .map(|fields| fields.apply(line, self.delimiter.as_deref()))
+            };
+
+            match value {
+                Some(value) => result.push_str(&shell_quote(&value)),
+                None => result.push_str(&rest[..=end]),
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        result.push_str(rest);
+        result
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FieldRange {
+    start: Option<i32>,
+    end: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fields(Vec<FieldRange>);
+
+#[derive(Debug)]
+pub struct FieldsError(String);
+
+impl fmt::Display for FieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid field selection '{}'", self.0)
+    }
+}
+
+impl std::error::Error for FieldsError {}
+
+impl Fields {
+    pub fn parse(value: &str) -> Result<Self, FieldsError> {
+        let err = || FieldsError(value.to_string());
+
+        let index = |s: &str| -> Result<Option<i32>, FieldsError> {
+            match s.trim() {
+                "" => Ok(None),
+                s => match s.parse::<i32>() {
+                    Ok(0) | Err(_) => Err(err()),
+                    Ok(i) => Ok(Some(i)),
+                },
+            }
+        };
+
+        let ranges = value
+            .split(',')
+            .map(|part| match part.split_once("..") {
+                Some((start, end)) => Ok(FieldRange {
+                    start: index(start)?,
+                    end: index(end)?,
+                }),
+                None => match index(part)? {
+                    Some(i) => Ok(FieldRange {
+                        start: Some(i),
+                        end: Some(i),
+                    }),
+                    None => Err(err()),
+                },
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Fields(ranges))
+    }
+
+    /// The selected fields of `line`, joined by the delimiter.
+    pub fn apply(&self, line: &str, delimiter: Option<&str>) -> String {
+        let parts = split(line, delimiter);
+        let len = parts.len() as i32;
+
+        // 1-based or negative index to 0-based, clamped to the line
+        let resolve = |i: i32| if i > 0 { i - 1 } else { len + i };
+
+        let selected: Vec<&str> = self
+            .0
+            .iter()
+            .flat_map(|range| {
+                let start = range.start.map_or(0, resolve).max(0);
+                let end = range.end.map_or(len - 1, resolve).min(len - 1);
+
+                (start..=end).map(|i| parts[i as usize])
+            })
+            .collect();
+
+        selected.join(delimiter.unwrap_or(" "))
+    }
+}
+
+fn split<'a>(line: &'a str, delimiter: Option<&str>) -> Vec<&'a str> {
+    match delimiter {
+        Some(d) if !d.is_empty() => line.split(d).collect(),
+        _ => line.split_whitespace().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Select `fields` of `line`, the selection must parse.
+    fn apply(fields: &str, line: &str, delimiter: Option<&str>) -> String {
+        Fields::parse(fields).unwrap().apply(line, delimiter)
+    }
+
+    /// What `sh` makes of `quoted` as a single argument.
+    fn shell_echo(quoted: &str) -> String {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf %s {quoted}"))
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn shell_quote_values() {
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+
+        for value in [
+            "",
+            "a b",
+            "it's",
+            "''",
+            "$(touch walker-injected)",
+            "`touch walker-injected`",
+            "$HOME",
+            "a\nb",
+            "a\\b; exit 1",
+        ] {
+            assert_eq!(shell_echo(&shell_quote(value)), value);
+        }
+    }
+
+    #[test]
+    fn preview_placeholders() {
+        let format = LineFormat {
+            delimiter: Some(":".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(format.preview_command("cat {}", "a:b c"), "cat 'a:b c'");
+        assert_eq!(format.preview_command("{1} {-1}", "a:b:c"), "'a' 'c'");
+        assert_eq!(format.preview_command("{2..}", "a:b:c"), "'b:c'");
+        assert_eq!(format.preview_command("echo {3}", "a:b"), "echo ''");
+        assert_eq!(format.preview_command("echo {}", ""), "echo ''");
+        assert_eq!(format.preview_command("echo", "a"), "echo");
+    }
+
+    #[test]
+    fn preview_keeps_other_braces() {
+        let format = LineFormat::default();
+
+        assert_eq!(
+            format.preview_command("awk '{print $1}' {}", "a"),
+            "awk '{print $1}' 'a'"
+        );
+        assert_eq!(format.preview_command("{0} {x} {}", "a"), "{0} {x} 'a'");
+        assert_eq!(format.preview_command("echo {} {", "a"), "echo 'a' {");
+    }
+
+    #[test]
+    fn preview_quotes_the_line() {
+        let format = LineFormat::default();
+
+        for line in [
+            "it's",
+            "$(touch walker-injected)",
+            "`touch walker-injected`",
+            "a\nb",
+            "",
+        ] {
+            let command = format.preview_command("printf %s {}", line);
+            assert_eq!(shell_echo(&command["printf %s ".len()..]), line);
+        }
+    }
+
+    /// Owned copies of `values`.
+    fn lines(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn expect_keys() {
+        let keys = parse_expect("ctrl-d, alt-Return").unwrap();
+
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].name, "ctrl-d");
+        assert_eq!(keys[0].modifier, ModifierType::CONTROL_MASK);
+        assert_eq!(keys[1].name, "alt-Return");
+        assert_eq!(keys[1].key, Key::Return);
+        assert_eq!(keys[1].modifier, ModifierType::ALT_MASK);
+        assert!(keys.iter().all(|k| k.exit_code.is_none()));
+
+        assert!(parse_expect("ctrl-nokey").is_err());
+        assert!(parse_expect("ctrl-d,").is_err());
+    }
+
+    #[test]
+    fn accept_binds() {
+        let keys = parse_accept_binds("ctrl-d:10,alt-Return: 11").unwrap();
+
+        assert_eq!(keys[0].name, "ctrl-d");
+        assert_eq!(keys[0].exit_code, Some(10));
+        assert_eq!(keys[1].key, Key::Return);
+        assert_eq!(keys[1].exit_code, Some(11));
+
+        // the exit code is split off last, so the key may contain colons
+        let keys = parse_accept_binds("ctrl-colon:3").unwrap();
+        assert_eq!(keys[0].name, "ctrl-colon");
+        assert_eq!(keys[0].exit_code, Some(3));
+
+        assert!(parse_accept_binds("ctrl-d").is_err());
+        assert!(parse_accept_binds("ctrl-d:").is_err());
+        assert!(parse_accept_binds("ctrl-d:x").is_err());
+        assert!(parse_accept_binds("ctrl-d:10,ctrl-e").is_err());
+    }
+
+    #[test]
+    fn accept_results() {
+        let expect = parse_expect("ctrl-d").unwrap();
+        let binds = parse_accept_binds("ctrl-e:10").unwrap();
+        let both = [expect.clone(), binds.clone()].concat();
+
+        let result = result_for(&[], lines(&["a"]), None);
+        assert_eq!(result.lines, ["a"]);
+        assert_eq!(result.exit_code, 0);
+
+        // Return with --expect prints an empty first line
+        let result = result_for(&expect, lines(&["a", "b"]), None);
+        assert_eq!(result.lines, ["", "a", "b"]);
+        assert_eq!(result.exit_code, 0);
+
+        let result = result_for(&expect, lines(&["a"]), Some(&expect[0]));
+        assert_eq!(result.lines, ["ctrl-d", "a"]);
+
+        let result = result_for(&binds, lines(&["a"]), Some(&binds[0]));
+        assert_eq!(result.lines, ["a"]);
+        assert_eq!(result.exit_code, 10);
+
+        let result = result_for(&both, lines(&["a"]), Some(&binds[0]));
+        assert_eq!(result.lines, ["", "a"]);
+        assert_eq!(result.exit_code, 10);
+
+        let result = result_for(&expect, Vec::new(), Some(&expect[0]));
+        assert!(result.lines.is_empty());
+        assert_eq!(result.exit_code, 0);
+    }
+
+    #[test]
+    fn fields_parse() {
+        let range = |start, end| FieldRange { start, end };
+
+        assert_eq!(Fields::parse("2").unwrap().0, [range(Some(2), Some(2))]);
+        assert_eq!(Fields::parse("-1").unwrap().0, [range(Some(-1), Some(-1))]);
+        assert_eq!(Fields::parse("2..").unwrap().0, [range(Some(2), None)]);
+        assert_eq!(Fields::parse("..3").unwrap().0, [range(None, Some(3))]);
+        assert_eq!(Fields::parse("..").unwrap().0, [range(None, None)]);
+        assert_eq!(
+            Fields::parse("1, 3..4").unwrap().0,
+            [range(Some(1), Some(1)), range(Some(3), Some(4))]
+        );
+
+        for invalid in ["", "0", "a", "1..b", "0..2", "1,,2", "1...2"] {
+            assert!(Fields::parse(invalid).is_err(), "{invalid:?} parsed");
+        }
+    }
+
+    #[test]
+    fn fields_apply() {
+        let line = "a b  c d";
+
+        assert_eq!(apply("1", line, None), "a");
+        assert_eq!(apply("-1", line, None), "d");
+        assert_eq!(apply("2..3", line, None), "b c");
+        assert_eq!(apply("2..", line, None), "b c d");
+        assert_eq!(apply("..2", line, None), "a b");
+        assert_eq!(apply("-2..", line, None), "c d");
+        assert_eq!(apply("3,1", line, None), "c a");
+    }
+
+    #[test]
+    fn fields_out_of_range() {
+        let line = "a b";
+
+        assert_eq!(apply("3", line, None), "");
+        assert_eq!(apply("-3", line, None), "");
+        assert_eq!(apply("2..5", line, None), "b");
+        assert_eq!(apply("-5..1", line, None), "a");
+        assert_eq!(apply("3..", line, None), "");
+        assert_eq!(apply("2..1", line, None), "");
+        assert_eq!(apply("1", "", None), "");
+    }
+
+    #[test]
+    fn fields_delimiter() {
+        let line = "1\tfirst item\t\tlast";
+
+        assert_eq!(apply("2", line, Some("\t")), "first item");
+        assert_eq!(apply("3", line, Some("\t")), "");
+        assert_eq!(apply("-1", line, Some("\t")), "last");
+        assert_eq!(apply("1,2", line, Some("\t")), "1\tfirst item");
+        assert_eq!(apply("2", "a::b::c", Some("::")), "b");
+
+        // an empty delimiter splits on whitespace, but joins with nothing
+        assert_eq!(apply("1..2", "a b", Some("")), "ab");
+    }
+}
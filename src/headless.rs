@@ -0,0 +1,332 @@
+//! Running walker without a window, f.e.
+//! `walker --query "fire" --provider desktopapplications --json`.
+//!
+//! Results are printed as one JSON object per line. `--nth` activates the nth
+//! result with its default action afterwards.
+//!
+//! `walker --activate <provider>:<identifier> --action <name>` activates an
+//! item directly, without querying first.
+//!
+//! `walker --dmenu --filter <query>` ranks stdin like the dmenu window would and
+//! prints the matches, best first.
+
+use std::io::{self, BufRead};
+
+use gtk4::glib::{VariantDict, VariantTy};
+
+use serde_json::json;
+
+use crate::config::{self, LoadError, get_config};
+use crate::data::{activate_once, activate_request, query_once};
+use crate::dmenu::{Fields, LineFormat};
+use crate::matching::{CaseMode, MatchMode, Order, Tiebreak, rank};
+use crate::protos::generated_proto::activate::ActivateRequest;
+use crate::protos::generated_proto::query::query_response::{Item, Type};
+use crate::providers::{get_providers, installed_providers, setup_providers};
+use crate::state::init_app_state;
+
+const SUCCESS: i32 = 0;
+const FAILURE: i32 = 1;
+
+/// Whether the options ask for a headless query or activation instead of the UI.
+pub fn is_requested(options: &VariantDict) -> bool {
+    let dmenu = options.contains("dmenu");
+
+    options.contains("activate")
+        || (options.contains("json") && !dmenu)
+        || (options.contains("filter") && dmenu)
+}
+
+/// Run the headless query, activation or filter. Returns the exit code.
+pub fn run(options: &VariantDict) -> i32 {
+    init_app_state();
+
+    match config::load() {
+        Ok(()) => {}
+        Err(e @ LoadError::UserConfig(_)) => eprintln!("{e}"),
+        Err(e) => {
+            eprintln!("{e}");
+            return FAILURE;
+        }
+    }
+
+    if let Some(query) = string(options, "filter") {
+        return run_filter(options, &query);
+    }
+
+    match string(options, "activate") {
+        Some(target) => run_activate(options, &target),
+        None => run_query(options),
+    }
+}
+
+fn run_query(options: &VariantDict) -> i32 {
+    let query = string(options, "query").unwrap_or_default();
+    let provider = string(options, "provider").unwrap_or_default();
+    let set = string(options, "set").unwrap_or_default();
+
+    let nth = match options
+        .lookup_value("nth", Some(VariantTy::INT64))
+        .and_then(|v| v.get::<i64>())
+    {
+        Some(n) if n > 0 => Some(n as usize),
+        Some(_) => {
+            eprintln!("--nth has to be a number greater than 0");
+            return FAILURE;
+        }
+        None => None,
+    };
+
+    if !set.is_empty() && !get_config().providers.sets.contains_key(&set) {
+        eprintln!("set '{set}' not found");
+        return FAILURE;
+    }
+
+    setup_providers(installed_providers(true));
+
+    let results = match query_once(&query, &provider, &set) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("query failed: {e}");
+            return FAILURE;
+        }
+    };
+
+    for resp in &results {
+        println!("{}", item_json(&resp.item));
+    }
+
+    let Some(n) = nth else {
+        return SUCCESS;
+    };
+
+    let Some(item) = results.get(n - 1).and_then(|resp| resp.item.as_ref()) else {
+        eprintln!("no result {n}, got {}", results.len());
+        return FAILURE;
+    };
+
+    let Some(action) = default_action(item) else {
+        eprintln!("result {n} has no actions");
+        return FAILURE;
+    };
+
+    let single = !provider.is_empty();
+
+    send_activation(activate_request(item, &query, &action, single))
+}
+
+fn run_filter(options: &VariantDict, query: &str) -> i32 {
+    let delimiter = string(options, "delimiter");
+
+    let fields = |name: &str| match string(options, name).as_deref().map(Fields::parse) {
+        Some(Ok(fields)) => Ok(Some(fields)),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    };
+
+    let (with_nth, accept_nth) = match (fields("with-nth"), fields("accept-nth")) {
+        (Ok(with_nth), Ok(accept_nth)) => (with_nth, accept_nth),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{e}");
+            return FAILURE;
+        }
+    };
+
+    let mode = match string(options, "match").as_deref().map(MatchMode::parse) {
+        Some(Some(mode)) => mode,
+        Some(None) => {
+            eprintln!("unknown match mode");
+            return FAILURE;
+        }
+        None => get_config().match_mode,
+    };
+
+    let case = match string(options, "match-case")
+        .as_deref()
+        .map(CaseMode::parse)
+    {
+        Some(Some(case)) => case,
+        Some(None) => {
+            eprintln!("unknown match case");
+            return FAILURE;
+        }
+        None => get_config().match_case,
+    };
+
+    let tiebreak = match string(options, "tiebreak")
+        .as_deref()
+        .map(Tiebreak::parse_list)
+    {
+        Some(Ok(tiebreak)) => tiebreak,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            return FAILURE;
+        }
+        None => Vec::new(),
+    };
+
+    let order = Order {
+        no_sort: options.contains("no-sort"),
+        tiebreak,
+    };
+
+    let format = LineFormat {
+        json: options.contains("json"),
+        delimiter: delimiter.clone(),
+        with_nth,
+        preview: None,
+    };
+
+    // (input index, item), indices count skipped lines like the window does
+    let mut items = Vec::new();
+
+    for (i, line) in io::stdin().lock().lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Error reading: {e}");
+                return FAILURE;
+            }
+        };
+
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match format.item(line) {
+            Ok(item) => items.push((i, item)),
+            Err(e) => eprintln!("invalid line {}: {e}", i + 1),
+        }
+    }
+
+    let matches = rank(
+        query,
+        mode,
+        case,
+        &order,
+        items.iter().map(|(_, item)| item.text.as_str()),
+    );
+
+    let index = options.contains("index");
+    let scores = options.contains("scores");
+
+    for m in &matches {
+        let (i, item) = &items[m.index];
+
+        let value = if index {
+            i.to_string()
+        } else {
+            match &accept_nth {
+                Some(fields) => fields.apply(&item.identifier, delimiter.as_deref()),
+                None => item.identifier.clone(),
+            }
+        };
+
+        if scores {
+            println!("{}\t{value}", m.score);
+        } else {
+            println!("{value}");
+        }
+    }
+
+    // like fzf, no match is a failure so scripts can branch on it
+    if matches.is_empty() { FAILURE } else { SUCCESS }
+}
+
+fn run_activate(options: &VariantDict, target: &str) -> i32 {
+    let Some((provider, identifier)) = target
+        .split_once(':')
+        .filter(|(p, i)| !p.is_empty() && !i.is_empty())
+    else {
+        eprintln!("--activate expects <provider>:<identifier>");
+        return FAILURE;
+    };
+
+    let mut item = Item::new();
+    item.provider = provider.to_string();
+    item.identifier = identifier.to_string();
+
+    let action = match string(options, "action") {
+        Some(action) => action,
+        None => {
+            setup_providers(installed_providers(true));
+
+            let Some(action) = get_providers().get(provider).and_then(|p| {
+                p.get_actions()
+                    .into_iter()
+                    .find(|a| a.default.unwrap_or(false))
+                    .map(|a| a.action)
+            }) else {
+                eprintln!("no --action given and '{provider}' has no default action");
+                return FAILURE;
+            };
+
+            action
+        }
+    };
+
+    let query = string(options, "query").unwrap_or_default();
+    let mut req = activate_request(&item, &query, &action, true);
+
+    if let Some(arguments) = string(options, "arguments") {
+        req.arguments = arguments;
+    }
+
+    send_activation(req)
+}
+
+fn send_activation(req: ActivateRequest) -> i32 {
+    match activate_once(req) {
+        Ok(()) => SUCCESS,
+        Err(e) => {
+            eprintln!("activation failed: {e}");
+            FAILURE
+        }
+    }
+}
+
+/// The action Return would run on `item` in the UI.
+fn default_action(item: &Item) -> Option<String> {
+    let providers = get_providers();
+    let actions = providers
+        .get(&item.provider)?
+        .get_keybind_hint(&item.actions);
+
+    let action = if item.actions.len() == 1 {
+        actions.iter().find(|a| a.action == item.actions[0])
+    } else {
+        actions.iter().find(|a| a.default.unwrap_or(false))
+    };
+
+    action.map(|a| a.action.clone())
+}
+
+fn item_json(item: &Item) -> serde_json::Value {
+    let item_type = match item.type_.enum_value_or_default() {
+        Type::REGULAR => "regular",
+        Type::FILE => "file",
+    };
+
+    json!({
+        "identifier": item.identifier,
+        "text": item.text,
+        "subtext": item.subtext,
+        "icon": item.icon,
+        "provider": item.provider,
+        "score": item.score,
+        "type": item_type,
+        "mimetype": item.mimetype,
+        "preview": item.preview,
+        "preview_type": item.preview_type,
+        "state": item.state,
+        "actions": item.actions,
+    })
+}
+
+fn string(options: &VariantDict, name: &str) -> Option<String> {
+    options
+        .lookup_value(name, Some(VariantTy::STRING))
+        .and_then(|v| v.get::<String>())
+}
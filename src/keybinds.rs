@@ -1,5 +1,5 @@
 use crate::config::get_config;
-use crate::providers::PROVIDERS;
+use crate::providers::get_providers;
 use crate::state::get_global_provider_actions;
 use gtk4::gdk::{self, Key};
 use serde::{Deserialize, Serialize};
@@ -19,6 +19,9 @@ pub const ACTION_QUICK_ACTIVATE: &str = "%QUICK_ACTIVATE%";
 pub const ACTION_SELECT_PAGE_DOWN: &str = "%PAGE_DOWN%";
 pub const ACTION_SELECT_PAGE_UP: &str = "%PAGE_UP%";
 pub const ACTION_SHOW_ACTIONS: &str = "%SHOW_ACTIONS%";
+pub const ACTION_RETRY_CONNECTION: &str = "%RETRY_CONNECTION%";
+pub const ACTION_TOGGLE_MARK: &str = "%TOGGLE_MARK%";
+pub const ACTION_CYCLE_MATCH_MODE: &str = "%CYCLE_MATCH_MODE%";
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub enum AfterAction {
@@ -72,12 +75,25 @@ pub static MODIFIERS: LazyLock<HashMap<&'static str, gdk::ModifierType>> = LazyL
     map
 });
 
-pub fn setup_binds() {
-    PROVIDERS.get().unwrap().iter().for_each(|(k, v)| {
-        v.get_actions().iter().for_each(|v| {
-            parse_bind(v, k).unwrap();
-        });
-    });
+/// Keybind tables, built up before they replace the current ones.
+#[derive(Default)]
+struct Tables {
+    binds: HashMap<Key, HashMap<gdk::ModifierType, Action>>,
+    grid_binds: HashMap<Key, HashMap<gdk::ModifierType, Action>>,
+    provider_binds: HashMap<String, HashMap<Key, HashMap<gdk::ModifierType, Vec<Action>>>>,
+}
+
+/// Build the keybind tables. Replaces existing tables, so it can be called again
+/// after the providers changed. If a bind doesn't parse, the existing tables
+/// are kept.
+pub fn setup_binds() -> Result<(), String> {
+    let mut tables = Tables::default();
+
+    get_providers().iter().try_for_each(|(k, v)| {
+        v.get_actions()
+            .iter()
+            .try_for_each(|v| parse_bind(&mut tables, v, k))
+    })?;
 
     let config = get_config();
 
@@ -87,12 +103,11 @@ pub fn setup_binds() {
         .get("fallback")
         .unwrap_or(&Vec::new())
         .iter()
-        .for_each(|v| {
-            parse_bind(v, "fallback").unwrap();
-        });
+        .try_for_each(|v| parse_bind(&mut tables, v, "fallback"))?;
 
-    config.keybinds.close.iter().for_each(|b| {
+    config.keybinds.close.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_CLOSE.to_string(),
                 unset: None,
@@ -103,11 +118,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.show_actions.iter().for_each(|b| {
+    config.keybinds.show_actions.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SHOW_ACTIONS.to_string(),
                 unset: None,
@@ -118,11 +133,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.next.iter().for_each(|b| {
+    config.keybinds.next.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_NEXT.to_string(),
                 unset: None,
@@ -133,11 +148,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.left.iter().for_each(|b| {
+    config.keybinds.left.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_LEFT.to_string(),
                 unset: None,
@@ -148,11 +163,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.right.iter().for_each(|b| {
+    config.keybinds.right.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_RIGHT.to_string(),
                 unset: None,
@@ -163,11 +178,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.up.iter().for_each(|b| {
+    config.keybinds.up.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_UP.to_string(),
                 unset: None,
@@ -178,11 +193,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.down.iter().for_each(|b| {
+    config.keybinds.down.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_DOWN.to_string(),
                 unset: None,
@@ -193,11 +208,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.previous.iter().for_each(|b| {
+    config.keybinds.previous.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_PREVIOUS.to_string(),
                 unset: None,
@@ -208,11 +223,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.toggle_exact.iter().for_each(|b| {
+    config.keybinds.toggle_exact.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_TOGGLE_EXACT.to_string(),
                 unset: None,
@@ -223,11 +238,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.resume_last_query.iter().for_each(|b| {
+    config.keybinds.resume_last_query.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_RESUME_LAST_QUERY.to_string(),
                 unset: None,
@@ -238,11 +253,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.page_down.iter().for_each(|b| {
+    config.keybinds.page_down.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 action: ACTION_SELECT_PAGE_DOWN.to_string(),
                 default: None,
@@ -253,11 +268,11 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
 
-    config.keybinds.page_up.iter().for_each(|b| {
+    config.keybinds.page_up.iter().try_for_each(|b| {
         parse_bind(
+            &mut tables,
             &Action {
                 unset: None,
                 action: ACTION_SELECT_PAGE_UP.to_string(),
@@ -268,14 +283,59 @@ pub fn setup_binds() {
             },
             "",
         )
-        .unwrap();
-    });
+    })?;
+
+    config.keybinds.retry_connection.iter().try_for_each(|b| {
+        parse_bind(
+            &mut tables,
+            &Action {
+                unset: None,
+                action: ACTION_RETRY_CONNECTION.to_string(),
+                default: None,
+                bind: Some(b.clone()),
+                label: Some("retry connection".to_string()),
+                after: Some(AfterAction::Nothing),
+            },
+            "",
+        )
+    })?;
+
+    config.keybinds.toggle_mark.iter().try_for_each(|b| {
+        parse_bind(
+            &mut tables,
+            &Action {
+                unset: None,
+                action: ACTION_TOGGLE_MARK.to_string(),
+                default: None,
+                bind: Some(b.clone()),
+                label: Some("mark".to_string()),
+                after: Some(AfterAction::Nothing),
+            },
+            "",
+        )
+    })?;
+
+    config.keybinds.cycle_match_mode.iter().try_for_each(|b| {
+        parse_bind(
+            &mut tables,
+            &Action {
+                unset: None,
+                action: ACTION_CYCLE_MATCH_MODE.to_string(),
+                default: None,
+                bind: Some(b.clone()),
+                label: Some("match mode".to_string()),
+                after: Some(AfterAction::Nothing),
+            },
+            "",
+        )
+    })?;
 
     if let Some(qa) = &config.keybinds.quick_activate {
-        qa.iter().enumerate().for_each(|(k, s)| {
+        qa.iter().enumerate().try_for_each(|(k, s)| {
             let action_str = format!("{ACTION_QUICK_ACTIVATE}:{k}");
 
             parse_bind(
+                &mut tables,
                 &Action {
                     default: None,
                     action: action_str,
@@ -286,12 +346,17 @@ pub fn setup_binds() {
                 },
                 "",
             )
-            .unwrap();
-        });
+        })?;
     }
+
+    *BINDS.write().unwrap() = tables.binds;
+    *GRID_BINDS.write().unwrap() = tables.grid_binds;
+    *PROVIDER_BINDS.write().unwrap() = tables.provider_binds;
+
+    Ok(())
 }
 
-fn parse_bind(b: &Action, provider: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn parse_bind(tables: &mut Tables, b: &Action, provider: &str) -> Result<(), String> {
     let mut b = b.clone();
 
     if let Some((first, _)) = b.action.split_once(":")
@@ -300,43 +365,11 @@ fn parse_bind(b: &Action, provider: &str) -> Result<(), Box<dyn std::error::Erro
         b.action = first.to_string();
     }
 
-    let mut fields = b.bind.as_ref().unwrap().split_whitespace().peekable();
-
-    if fields.peek().is_none() {
-        return Err("incorrect bind".into());
-    }
-
-    let mut modifiers_list = Vec::new();
-    let mut key: Option<Key> = None;
-
-    for field in fields {
-        if let Some(&modifier) = MODIFIERS.get(field) {
-            modifiers_list.push(modifier);
-            continue;
-        }
-
-        key = match Key::from_name(field.to_string()) {
-            Some(k) => Some(k),
-            None => {
-                eprintln!(
-                    "Keybind Error: unable to create key from name: '{}' in '{}'.",
-                    field,
-                    b.bind.as_ref().unwrap()
-                );
-                std::process::exit(1);
-            }
-        };
-    }
-
-    let modifier = modifiers_list
-        .iter()
-        .fold(gdk::ModifierType::empty(), |acc, &m| acc | m);
-
-    let key = key.ok_or("incorrect bind")?;
+    let (key, modifier) = parse_key(b.bind.as_ref().unwrap())?;
 
     if provider.is_empty() {
-        let mut binds = BINDS.write().unwrap();
-        let mut grid_binds = GRID_BINDS.write().unwrap();
+        let binds = &mut tables.binds;
+        let grid_binds = &mut tables.grid_binds;
 
         match b.action.as_str() {
             ACTION_SELECT_PREVIOUS | ACTION_SELECT_NEXT => {
@@ -360,9 +393,8 @@ fn parse_bind(b: &Action, provider: &str) -> Result<(), Box<dyn std::error::Erro
         return Ok(());
     }
 
-    let mut provider_binds = PROVIDER_BINDS.write().unwrap();
-
-    provider_binds
+    tables
+        .provider_binds
         .entry(provider.to_string())
         .or_default()
         .entry(key)
@@ -374,6 +406,35 @@ fn parse_bind(b: &Action, provider: &str) -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
+/// Parse a bind like `ctrl e` or `ctrl-e` into its key and modifiers.
+pub fn parse_key(bind: &str) -> Result<(Key, gdk::ModifierType), String> {
+    let mut modifier = gdk::ModifierType::empty();
+    let mut key: Option<Key> = None;
+
+    for field in bind.split(|c: char| c.is_whitespace() || c == '-') {
+        if field.is_empty() {
+            continue;
+        }
+
+        if let Some(&m) = MODIFIERS.get(field) {
+            modifier |= m;
+            continue;
+        }
+
+        match Key::from_name(field) {
+            Some(k) => key = Some(k),
+            None => {
+                return Err(format!(
+                    "unable to create key from name: '{field}' in '{bind}'."
+                ));
+            }
+        }
+    }
+
+    key.map(|key| (key, modifier))
+        .ok_or_else(|| format!("incorrect bind '{bind}'"))
+}
+
 pub fn get_show_actions_action() -> Action {
     BINDS
         .read()
@@ -1,19 +1,28 @@
+mod app_actions;
+mod commands;
 mod config;
 mod data;
+mod dmenu;
+mod headless;
 mod keybinds;
+mod matching;
 mod preview;
+mod protocol;
 mod protos;
 mod providers;
 mod renderers;
 mod state;
+mod subscriptions;
 mod theme;
 mod ui;
-use gtk4::gio::prelude::{ApplicationCommandLineExt, DataInputStreamExtManual, SettingsExt};
+use gtk4::gio::prelude::{
+    ApplicationCommandLineExt, BufferedInputStreamExt, DataInputStreamExtManual, SettingsExt,
+};
 use gtk4::gio::{self, ApplicationCommandLine, ApplicationHoldGuard};
 use gtk4::glib::Priority;
-use gtk4::prelude::EntryExt;
+use gtk4::prelude::{Cast, EditableExt, EntryExt, ListModelExt};
 
-use config::get_config;
+use config::{LoadError, get_config};
 use state::init_app_state;
 use which::which;
 
@@ -38,32 +47,42 @@ use gtk4::{
     prelude::WidgetExt,
 };
 
-use crate::data::init_socket;
+use crate::app_actions::setup_app_actions;
+use crate::commands::serve_client;
+use crate::data::{init_socket, refresh_providers};
+use crate::dmenu::{AcceptKey, DmenuResult, Fields, LineFormat, parse_accept_binds, parse_expect};
 use crate::keybinds::setup_binds;
+use crate::matching::{CaseMode, MatchMode, Order, Tiebreak};
 use crate::protos::QueryResponseObject;
-use crate::protos::generated_proto::query::{QueryResponse, query_response};
-use crate::providers::setup_providers;
+use crate::protos::generated_proto::query::QueryResponse;
+use crate::providers::{get_providers, known_providers, setup_providers};
 use crate::state::{
     get_last_query, get_parameter_height, get_parameter_max_height, get_parameter_max_width,
-    get_parameter_min_height, get_parameter_min_width, get_parameter_width, get_placeholder,
-    get_provider, get_theme, has_elephant, has_theme, is_connected, is_dmenu, is_dmenu_keep_open,
-    is_emergency, is_input_only, is_no_hints, is_no_search, is_param_close, is_service,
-    is_stay_open_explicit_provider, is_visible, set_current_set, set_dmenu_current,
-    set_dmenu_exit_after, set_dmenu_keep_open, set_error, set_has_elephant, set_hide_qa, set_index,
-    set_initial_height, set_initial_max_height, set_initial_max_width, set_initial_min_height,
-    set_initial_min_width, set_initial_placeholder, set_initial_width, set_input_only,
-    set_is_dmenu, set_is_emergency, set_is_service, set_is_stay_open_explicit_provider,
-    set_is_visible, set_no_hints, set_no_search, set_param_close, set_parameter_height,
-    set_parameter_max_height, set_parameter_max_width, set_parameter_min_height,
-    set_parameter_min_width, set_parameter_width, set_placeholder, set_provider, set_theme,
+    get_parameter_min_height, get_parameter_min_width, get_parameter_query, get_parameter_width,
+    get_placeholder, get_provider, get_theme, has_elephant, has_theme, is_connected, is_dmenu,
+    is_dmenu_keep_open, is_emergency, is_input_only, is_no_hints, is_no_search, is_param_close,
+    is_password, is_select_query, is_service, is_stay_open_explicit_provider, is_visible,
+    set_current_set, set_dmenu_accept_keys, set_dmenu_accept_nth, set_dmenu_current,
+    set_dmenu_delimiter, set_dmenu_exit_after, set_dmenu_keep_open, set_dmenu_order, set_error,
+    set_has_elephant, set_hide_qa, set_index, set_initial_height, set_initial_max_height,
+    set_initial_max_width, set_initial_min_height, set_initial_min_width, set_initial_placeholder,
+    set_initial_width, set_input_only, set_is_dmenu, set_is_emergency, set_is_service,
+    set_is_stay_open_explicit_provider, set_is_visible, set_match_case, set_match_mode, set_multi,
+    set_no_hints, set_no_search, set_param_close, set_parameter_height, set_parameter_max_height,
+    set_parameter_max_width, set_parameter_min_height, set_parameter_min_width,
+    set_parameter_query, set_parameter_width, set_password, set_placeholder, set_provider,
+    set_select_query, set_theme,
 };
+use crate::subscriptions::setup_subscriptions;
 use crate::theme::{setup_css, setup_css_provider, setup_themes};
+use crate::ui::local_filter::update_match_mode_class;
 use crate::ui::window::{
     check_error, handle_grid_setting, quit, set_input_text, set_keybind_hint, setup_window,
-    with_window,
+    update_mark_counter, with_window,
 };
 
-static GLOBAL_DMENU_SENDER: RwLock<Option<Sender<String>>> = RwLock::new(None);
+/// Hands the result of a dmenu call to the waiting command line.
+static GLOBAL_DMENU_SENDER: RwLock<Option<Sender<DmenuResult>>> = RwLock::new(None);
 
 thread_local! {
     static HOLD_GUARD: OnceCell<ApplicationHoldGuard> = const { OnceCell::new() };
@@ -75,7 +94,13 @@ fn main() -> glib::ExitCode {
         .flags(ApplicationFlags::HANDLES_COMMAND_LINE)
         .build();
 
-    app.connect_handle_local_options(|_, _| -1);
+    app.connect_handle_local_options(|_, options| {
+        if headless::is_requested(options) {
+            return headless::run(options);
+        }
+
+        -1
+    });
 
     add_flags(&app);
 
@@ -91,10 +116,19 @@ fn init_ui(app: &Application, dmenu: bool, theme: &str) {
         set_is_service(true);
     }
 
-    config::load().unwrap();
+    match config::load() {
+        Ok(()) => {}
+        Err(LoadError::UserConfig(e)) => {
+            set_error(format!("Config: {e}"));
+            println!("{e}");
+        }
+        Err(e) => panic!("{e}"),
+    }
+
+    let cfg = get_config();
 
     let mut theme = if theme.is_empty() {
-        get_config().theme.as_str()
+        cfg.theme.as_str()
     } else {
         theme
     };
@@ -112,11 +146,21 @@ fn init_ui(app: &Application, dmenu: bool, theme: &str) {
         set_has_elephant(elephant);
     }
 
-    setup_providers(elephant);
+    // elephant is asked in the background, its providers are set up once it answers
+    setup_providers(known_providers());
+
+    if elephant {
+        refresh_providers();
+    }
+
+    setup_subscriptions();
 
     setup_css_provider();
 
-    setup_binds();
+    if let Err(e) = setup_binds() {
+        eprintln!("Keybind Error: {e}");
+        process::exit(1);
+    }
 
     setup_themes(elephant && !dmenu, get_theme(), is_service());
 
@@ -139,6 +183,29 @@ fn init_ui(app: &Application, dmenu: bool, theme: &str) {
     }
 }
 
+/// Rebuild everything derived from the installed providers: the registry,
+/// keybinds, theme item layouts and subscriptions. Shown results of providers
+/// that are gone are dropped. If a keybind doesn't parse, the previous keybinds
+/// stay and the error is returned.
+fn reload_providers(provider_list: Vec<String>) -> Result<(), String> {
+    setup_providers(provider_list);
+    let binds = setup_binds().map_err(|e| format!("keybind error: {e}"));
+    setup_themes(has_elephant(), get_theme(), is_service());
+    setup_subscriptions();
+
+    let providers = get_providers();
+
+    with_window(|w| {
+        w.items.retain(|obj| {
+            obj.downcast_ref::<QueryResponseObject>()
+                .and_then(|obj| obj.response().item.into_option())
+                .is_none_or(|item| providers.contains_key(&item.provider))
+        });
+    });
+
+    binds
+}
+
 fn adjust_accent_color(settings: &gio::Settings) {
     with_window(|w| {
         w.window
@@ -164,7 +231,7 @@ fn adjust_color_scheme(settings: &gio::Settings) {
     });
 }
 
-fn send_message(message: String) {
+fn send_message(message: DmenuResult) {
     let mut sender_guard = GLOBAL_DMENU_SENDER.write().unwrap();
 
     if let Some(sender) = sender_guard.take() {
@@ -224,6 +291,132 @@ fn add_flags(app: &Application) {
         None,
     );
 
+    app.add_main_option(
+        "multi",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "allow marking several items, all marked ones are printed. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "delimiter",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "field delimiter for --with-nth and --accept-nth, defaults to whitespace. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "with-nth",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "fields to show and match on, f.e. '2..' or '1,3'. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "accept-nth",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "fields to print on accept. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "preview",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "preview command, {} is replaced by the line, {1} etc. by its fields. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "expect",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "keys that also accept, f.e. 'ctrl-d,alt-Return'. the key is printed first. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "bind",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "keys that accept with an exit code, f.e. 'ctrl-d:10'. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "filter",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "print the lines of stdin matching the query and exit. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "scores",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "print the score in front of every line. only with --filter.",
+        None,
+    );
+
+    app.add_main_option(
+        "match",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "how to match dmenu items: fuzzy, exact, prefix, word-prefix or regex.",
+        None,
+    );
+
+    app.add_main_option(
+        "match-case",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "case for --match: ignore, smart or respect.",
+        None,
+    );
+
+    app.add_main_option(
+        "no-sort",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "keep the input order while filtering. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "tiebreak",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "order of equal matches, f.e. 'length,begin'. length, begin or index. dmenu only.",
+        None,
+    );
+
+    app.add_main_option(
+        "password",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "mask the input and never remember it. dmenu only.",
+        None,
+    );
+
     app.add_main_option(
         "provider",
         b'm'.into(),
@@ -359,6 +552,78 @@ fn add_flags(app: &Application) {
         None,
     );
 
+    app.add_main_option(
+        "reload",
+        b'r'.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "reload providers, keybinds and theme layouts. only when using service.",
+        None,
+    );
+
+    app.add_main_option(
+        "query",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "initial query, prefixes included.",
+        None,
+    );
+
+    app.add_main_option(
+        "selectquery",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "select the initial query instead of placing the cursor at its end.",
+        None,
+    );
+
+    app.add_main_option(
+        "json",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::None,
+        "print the results of --query as json lines instead of opening walker. with --dmenu: read json lines.",
+        None,
+    );
+
+    app.add_main_option(
+        "nth",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::Int64,
+        "activate the nth result with its default action. only with --json.",
+        None,
+    );
+
+    app.add_main_option(
+        "activate",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "activate <provider>:<identifier> without opening walker.",
+        None,
+    );
+
+    app.add_main_option(
+        "action",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "action to run. only with --activate, defaults to the provider's default action.",
+        None,
+    );
+
+    app.add_main_option(
+        "arguments",
+        0u8.into(),
+        OptionFlags::NONE,
+        glib::OptionArg::String,
+        "arguments for the action. only with --activate.",
+        None,
+    );
+
     app.add_main_option(
         "exit",
         b'e'.into(),
@@ -377,6 +642,19 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
         return 0;
     }
 
+    if options.contains("reload") {
+        if let Err(e) = reload_providers(known_providers()) {
+            cmd.printerr_literal(&format!("{e}\n"));
+            return 1;
+        }
+
+        if has_elephant() {
+            refresh_providers();
+        }
+
+        return 0;
+    }
+
     set_is_stay_open_explicit_provider(false);
 
     if let Some(val) = options.lookup_value("provider", Some(VariantTy::STRING)) {
@@ -446,6 +724,37 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
         set_placeholder(val.str().unwrap().to_string());
     }
 
+    set_parameter_query(
+        options
+            .lookup_value("query", Some(VariantTy::STRING))
+            .and_then(|val| val.str().map(str::to_string)),
+    );
+    set_select_query(options.contains("selectquery"));
+
+    let lookup = |name: &str| {
+        options
+            .lookup_value(name, Some(VariantTy::STRING))
+            .and_then(|val| val.str().map(str::to_string))
+    };
+
+    match lookup("match").map(|v| MatchMode::parse(&v).ok_or(v)) {
+        Some(Ok(mode)) => set_match_mode(Some(mode)),
+        Some(Err(v)) => {
+            cmd.printerr_literal(&format!("unknown match mode '{v}'\n"));
+            return 1;
+        }
+        None => set_match_mode(None),
+    }
+
+    match lookup("match-case").map(|v| CaseMode::parse(&v).ok_or(v)) {
+        Some(Ok(case)) => set_match_case(Some(case)),
+        Some(Err(v)) => {
+            cmd.printerr_literal(&format!("unknown match case '{v}'\n"));
+            return 1;
+        }
+        None => set_match_case(None),
+    }
+
     'dmenu: {
         if !options.contains("dmenu") {
             set_dmenu_keep_open(false);
@@ -463,6 +772,74 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
         }
 
         set_index(options.contains("index"));
+        set_multi(options.contains("multi"));
+        set_password(options.contains("password"));
+
+        let delimiter = options
+            .lookup_value("delimiter", Some(VariantTy::STRING))
+            .and_then(|val| val.str().map(str::to_string));
+
+        let fields = |name: &str| match options.lookup_value(name, Some(VariantTy::STRING)) {
+            Some(val) => Fields::parse(val.str().unwrap()).map(Some),
+            None => Ok(None),
+        };
+
+        let (with_nth, accept_nth) = match (fields("with-nth"), fields("accept-nth")) {
+            (Ok(with_nth), Ok(accept_nth)) => (with_nth, accept_nth),
+            (Err(e), _) | (_, Err(e)) => {
+                cmd.printerr_literal(&format!("{e}\n"));
+                return 1;
+            }
+        };
+
+        let tiebreak = match options.lookup_value("tiebreak", Some(VariantTy::STRING)) {
+            Some(val) => match Tiebreak::parse_list(val.str().unwrap()) {
+                Ok(tiebreak) => tiebreak,
+                Err(e) => {
+                    cmd.printerr_literal(&format!("{e}\n"));
+                    return 1;
+                }
+            },
+            None => Vec::new(),
+        };
+
+        set_dmenu_order(Order {
+            no_sort: options.contains("no-sort"),
+            tiebreak,
+        });
+
+        let keys = |name: &str, parse: fn(&str) -> Result<Vec<AcceptKey>, String>| match options
+            .lookup_value(name, Some(VariantTy::STRING))
+        {
+            Some(val) => parse(val.str().unwrap()),
+            None => Ok(Vec::new()),
+        };
+
+        match (
+            keys("expect", parse_expect),
+            keys("bind", parse_accept_binds),
+        ) {
+            (Ok(mut expect), Ok(binds)) => {
+                expect.extend(binds);
+                set_dmenu_accept_keys(expect);
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                cmd.printerr_literal(&format!("{e}\n"));
+                return 1;
+            }
+        }
+
+        let format = LineFormat {
+            json: options.contains("json"),
+            delimiter: delimiter.clone(),
+            with_nth,
+            preview: options
+                .lookup_value("preview", Some(VariantTy::STRING))
+                .and_then(|val| val.str().map(str::to_string)),
+        };
+
+        set_dmenu_delimiter(delimiter);
+        set_dmenu_accept_nth(accept_nth);
 
         set_input_only(options.contains("inputonly"));
 
@@ -477,7 +854,7 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
         set_dmenu_exit_after(options.contains("exit"));
 
         if GLOBAL_DMENU_SENDER.read().unwrap().is_some() {
-            send_message("CNCLD".to_string());
+            send_message(DmenuResult::default());
             break 'dmenu;
         }
 
@@ -491,6 +868,7 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
 
             let items = w.items.clone();
             items.remove_all();
+            update_mark_counter();
 
             if is_input_only() {
                 return;
@@ -499,8 +877,17 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
             let stdin = cmd.stdin();
             let data_stream = gio::DataInputStream::new(&stdin.unwrap());
 
-            async fn read_lines_async(stream: Rc<gio::DataInputStream>, items: gio::ListStore) {
+            async fn read_lines_async(
+                stream: Rc<gio::DataInputStream>,
+                items: gio::ListStore,
+                format: LineFormat,
+            ) {
+                // every change filters and sorts again, so lines are added in batches
+                const BATCH_SIZE: usize = 1000;
+
                 let mut i = 0;
+                let mut batch = Vec::new();
+                let mut done = false;
 
                 loop {
                     match stream.read_line_utf8_future(Priority::DEFAULT).await {
@@ -512,22 +899,33 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
                             let line = line.trim();
 
                             if !line.is_empty() {
-                                let mut item = query_response::Item::new();
-                                item.text = line.to_string();
-                                item.provider = "dmenu".to_string();
-                                item.score = 1000000 - i;
-                                item.actions = vec!["select".to_string()];
-
-                                let mut response = QueryResponse::new();
-                                response.item = protobuf::MessageField::some(item);
-
-                                items.append(&QueryResponseObject::new(response));
+                                match format.item(line) {
+                                    Ok(mut item) => {
+                                        item.provider = "dmenu".to_string();
+                                        item.score = 1000000 - i;
+                                        item.actions = vec!["select".to_string()];
+
+                                        let mut response = QueryResponse::new();
+                                        response.item = protobuf::MessageField::some(item);
+
+                                        batch.push(QueryResponseObject::new(response));
+                                    }
+                                    Err(e) => eprintln!("invalid line {}: {e}", i + 1),
+                                }
                             }
 
                             i += 1;
+
+                            // show what's there before waiting for more input
+                            if batch.len() >= BATCH_SIZE
+                                || (!batch.is_empty() && stream.available() == 0)
+                            {
+                                items.splice(items.n_items(), 0, &batch);
+                                batch.clear();
+                            }
                         }
                         Ok(None) => {
-                            set_keybind_hint();
+                            done = true;
                             break;
                         }
                         Err(e) => {
@@ -536,10 +934,18 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
                         }
                     }
                 }
+
+                if !batch.is_empty() {
+                    items.splice(items.n_items(), 0, &batch);
+                }
+
+                if done {
+                    set_keybind_hint();
+                }
             }
 
             glib::spawn_future_local(async move {
-                read_lines_async(Rc::new(data_stream), items).await;
+                read_lines_async(Rc::new(data_stream), items, format).await;
             });
         });
 
@@ -555,12 +961,17 @@ fn handle_command_line(app: &Application, cmd: &ApplicationCommandLine) -> i32 {
 
         glib::spawn_future_local(async move {
             match receiver.await {
-                Ok(message) => match message.as_str() {
-                    "CNCLD" => {
-                        cmd.set_exit_status(130);
-                    }
-                    msg => cmd.print_literal(&format!("{msg}\n")),
-                },
+                Ok(result) if result.lines.is_empty() => {
+                    cmd.set_exit_status(130);
+                }
+                Ok(result) => {
+                    result
+                        .lines
+                        .iter()
+                        .for_each(|l| cmd.print_literal(&format!("{l}\n")));
+
+                    cmd.set_exit_status(result.exit_code);
+                }
                 Err(_) => {
                     println!("the sender dropped");
                     cmd.set_exit_status(130);
@@ -605,22 +1016,39 @@ fn activate(app: &Application) {
         handle_grid_setting();
     }
 
+    update_match_mode_class();
+
     with_window(|w| {
         setup_css(get_theme());
 
         if let Some(input) = &w.input {
-            if cfg.resume_last_query {
-                set_input_text(&get_last_query());
-            } else {
-                set_input_text("");
+            let query = get_parameter_query();
+
+            input.set_visibility(!is_password());
+
+            match &query {
+                Some(query) => set_input_text(query),
+                None if cfg.resume_last_query && !is_password() => {
+                    set_input_text(&get_last_query())
+                }
+                None => set_input_text(""),
             }
+
             input.grab_focus();
+
+            if query.is_some() {
+                if is_select_query() {
+                    input.select_region(0, -1);
+                } else {
+                    input.set_position(-1);
+                }
+            }
         }
 
         w.window.set_visible(true);
 
         if !is_dmenu() && !is_connected() && has_elephant() {
-            thread::spawn(|| init_socket().unwrap());
+            init_socket();
         } else if !has_elephant() && !is_dmenu() {
             println!("Please install elephant.");
             process::exit(1);
@@ -757,11 +1185,38 @@ fn startup(app: &Application) {
 
     init_app_state();
     init_ui(app, dmenu, theme);
+    setup_app_actions(app);
 
-    listen_activation_socket(app.clone());
+    listen_activation_socket();
+}
+
+/// Reset the per-invocation parameters to their defaults.
+fn reset_parameters() {
+    set_parameter_width(None);
+    set_parameter_height(None);
+    set_parameter_min_width(None);
+    set_parameter_max_width(None);
+    set_parameter_min_height(None);
+    set_parameter_max_height(None);
+    set_parameter_query(None);
+    set_select_query(false);
+    set_is_dmenu(false);
+    set_multi(false);
+    set_password(false);
+    set_match_mode(None);
+    set_match_case(None);
+    set_dmenu_order(Order::default());
+    set_dmenu_delimiter(None);
+    set_dmenu_accept_nth(None);
+    set_dmenu_accept_keys(Vec::new());
+    set_dmenu_keep_open(false);
+    set_param_close(false);
+    set_hide_qa(false);
+    set_provider("".to_string());
+    set_is_stay_open_explicit_provider(false);
 }
 
-fn listen_activation_socket(app_clone: Application) {
+fn listen_activation_socket() {
     let mut socket_path = env::var("XDG_RUNTIME_DIR")
         .map(PathBuf::from)
         .unwrap_or_else(|_| env::temp_dir());
@@ -784,21 +1239,7 @@ fn listen_activation_socket(app_clone: Application) {
         if condition.contains(glib::IOCondition::IN) {
             match listener.accept() {
                 Ok((stream, _)) => {
-                    drop(stream);
-                    set_parameter_width(None);
-                    set_parameter_height(None);
-                    set_parameter_min_width(None);
-                    set_parameter_max_width(None);
-                    set_parameter_min_height(None);
-                    set_parameter_max_height(None);
-                    set_is_dmenu(false);
-                    set_dmenu_keep_open(false);
-                    set_param_close(false);
-                    set_hide_qa(false);
-                    set_provider("".to_string());
-                    set_is_stay_open_explicit_provider(false);
-
-                    activate(&app_clone);
+                    thread::spawn(move || serve_client(stream));
                 }
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
                 Err(e) => {
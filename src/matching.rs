@@ -0,0 +1,640 @@
+//! Matching for lists that are filtered locally instead of by elephant, like
+//! dmenu, the emergency list, the actions menu and `--dmenu --filter`.
+
+use std::cmp::Ordering;
+
+use nucleo_matcher::pattern::{Atom, AtomKind, CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Matcher, Utf32Str};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// How the query is matched against the items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    #[default]
+    Fuzzy,
+    /// The query as a substring.
+    Exact,
+    /// The query at the start of the text.
+    Prefix,
+    /// Every word of the query at the start of a word in the text.
+    WordPrefix,
+    Regex,
+}
+
+/// Whether matching cares about case. `Smart` ignores it unless the query
+/// contains uppercase characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaseMode {
+    #[default]
+    Ignore,
+    Smart,
+    Respect,
+}
+
+impl MatchMode {
+    const ALL: [MatchMode; 5] = [
+        MatchMode::Fuzzy,
+        MatchMode::Exact,
+        MatchMode::Prefix,
+        MatchMode::WordPrefix,
+        MatchMode::Regex,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.name() == value)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MatchMode::Fuzzy => "fuzzy",
+            MatchMode::Exact => "exact",
+            MatchMode::Prefix => "prefix",
+            MatchMode::WordPrefix => "word-prefix",
+            MatchMode::Regex => "regex",
+        }
+    }
+
+    /// The mode after this one, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL
+            .iter()
+            .position(|m| *m == self)
+            .unwrap_or_default();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// The css class set on the window while this mode is active.
+    pub fn css_class(self) -> String {
+        format!("match-{}", self.name())
+    }
+
+    pub fn css_classes() -> impl Iterator<Item = String> {
+        Self::ALL.into_iter().map(Self::css_class)
+    }
+}
+
+impl CaseMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ignore" => Some(CaseMode::Ignore),
+            "smart" => Some(CaseMode::Smart),
+            "respect" => Some(CaseMode::Respect),
+            _ => None,
+        }
+    }
+
+    fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseMode::Ignore => true,
+            CaseMode::Smart => !query.chars().any(char::is_uppercase),
+            CaseMode::Respect => false,
+        }
+    }
+}
+
+impl From<CaseMode> for CaseMatching {
+    fn from(case: CaseMode) -> Self {
+        match case {
+            CaseMode::Ignore => CaseMatching::Ignore,
+            CaseMode::Smart => CaseMatching::Smart,
+            CaseMode::Respect => CaseMatching::Respect,
+        }
+    }
+}
+
+/// How matches with the same score are ordered, f.e. `--tiebreak length,begin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tiebreak {
+    /// Shorter text first.
+    Length,
+    /// Earlier match first.
+    Begin,
+    /// Input order.
+    Index,
+}
+
+impl Tiebreak {
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value
+            .split(',')
+            .map(|t| match t.trim() {
+                "length" => Ok(Tiebreak::Length),
+                "begin" => Ok(Tiebreak::Begin),
+                "index" => Ok(Tiebreak::Index),
+                t => Err(format!(
+                    "unknown tiebreak '{t}', expected length, begin or index"
+                )),
+            })
+            .collect()
+    }
+}
+
+/// How matches are ordered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Order {
+    /// Keep the input order instead of sorting by score.
+    pub no_sort: bool,
+    /// Applied in turn to equal scores, the input order decides last.
+    pub tiebreak: Vec<Tiebreak>,
+}
+
+impl Order {
+    /// Whether scoring has to find where matches begin.
+    pub fn needs_begin(&self) -> bool {
+        !self.no_sort && self.tiebreak.contains(&Tiebreak::Begin)
+    }
+
+    /// Compare two matches. `a_index` and `b_index` are their input positions.
+    pub fn compare(&self, a: &Score, a_index: i64, b: &Score, b_index: i64) -> Ordering {
+        let by_index = a_index.cmp(&b_index);
+
+        if self.no_sort {
+            return by_index;
+        }
+
+        self.tiebreak
+            .iter()
+            .fold(b.score.cmp(&a.score), |ord, tiebreak| {
+                ord.then_with(|| match tiebreak {
+                    Tiebreak::Length => a.len.cmp(&b.len),
+                    Tiebreak::Begin => a.begin.cmp(&b.begin),
+                    Tiebreak::Index => by_index,
+                })
+            })
+            .then(by_index)
+    }
+}
+
+/// A match's score and what the tiebreaks look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    pub score: u32,
+    /// Char position the match begins at, only set if the order needs it.
+    pub begin: u32,
+    /// Length of the text in chars.
+    pub len: u32,
+}
+
+/// A line that matched the query.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// Position of the line in the input.
+    pub index: usize,
+    pub score: u32,
+}
+
+enum Kind {
+    Fuzzy(Pattern),
+    Atom(Atom),
+    /// Not a nucleo prefix atom, nucleo 0.3 compares the whole text with the
+    /// query when it respects case.
+    Prefix {
+        prefix: Vec<char>,
+        ignore_case: bool,
+    },
+    WordPrefix {
+        words: Vec<Vec<char>>,
+        ignore_case: bool,
+    },
+    /// `None` while the query isn't a valid regex, which matches nothing.
+    Regex(Option<Regex>),
+}
+
+pub struct LocalMatcher {
+    kind: Kind,
+    min_score: u32,
+    track_begin: bool,
+    matcher: Matcher,
+    buf: Vec<char>,
+    indices: Vec<u32>,
+}
+
+impl LocalMatcher {
+    pub fn new(query: &str, mode: MatchMode, case: CaseMode) -> Self {
+        let atom = |kind| Atom::new(query, case.into(), Normalization::Smart, kind, false);
+        let ignore_case = case.ignores_case(query);
+
+        let kind = match mode {
+            MatchMode::Fuzzy => {
+                Kind::Fuzzy(Pattern::parse(query, case.into(), Normalization::Smart))
+            }
+            MatchMode::Exact => Kind::Atom(atom(AtomKind::Substring)),
+            MatchMode::Prefix => Kind::Prefix {
+                prefix: fold(query, ignore_case).collect(),
+                ignore_case,
+            },
+            MatchMode::WordPrefix => Kind::WordPrefix {
+                words: query
+                    .split_whitespace()
+                    .map(|w| fold(w, ignore_case).collect())
+                    .collect(),
+                ignore_case,
+            },
+            MatchMode::Regex => Kind::Regex(
+                RegexBuilder::new(query)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .ok(),
+            ),
+        };
+
+        // path bonuses only help if the query looks for a path, they skew plain text
+        let config = if query.contains('/') {
+            Config::DEFAULT.match_paths()
+        } else {
+            Config::DEFAULT
+        };
+
+        let min_score = match mode {
+            MatchMode::Fuzzy => 18 * query.chars().count() as u32,
+            _ => 0,
+        };
+
+        Self {
+            kind,
+            min_score,
+            track_begin: false,
+            matcher: Matcher::new(config),
+            buf: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Score `text`. Weak fuzzy matches below [`LocalMatcher::min_score`]
+    /// shouldn't be shown.
+    pub fn score(&mut self, text: &str) -> Option<u32> {
+        match &self.kind {
+            Kind::Fuzzy(pattern) => {
+                pattern.score(Utf32Str::new(text, &mut self.buf), &mut self.matcher)
+            }
+            Kind::Atom(atom) => atom
+                .score(Utf32Str::new(text, &mut self.buf), &mut self.matcher)
+                .map(u32::from),
+            Kind::Prefix { .. } | Kind::WordPrefix { .. } | Kind::Regex(_) => {
+                let (start, _) = self.plain_match(text)?;
+
+                // earlier matches rank higher
+                Some(u32::from(u16::MAX) - start.min(u32::from(u16::MAX)))
+            }
+        }
+    }
+
+    pub fn min_score(&self) -> u32 {
+        self.min_score
+    }
+
+    /// Also find where matches begin, for [`Tiebreak::Begin`].
+    pub fn with_begin(mut self, val: bool) -> Self {
+        self.track_begin = val;
+        self
+    }
+
+    /// Score `text` along with what the tiebreaks need.
+    pub fn matched(&mut self, text: &str) -> Option<Score> {
+        let score = self.score(text)?;
+
+        let begin = if self.track_begin {
+            self.positions(text).first().copied().unwrap_or_default()
+        } else {
+            0
+        };
+
+        Some(Score {
+            score,
+            begin,
+            len: text.chars().count() as u32,
+        })
+    }
+
+    /// The matched char positions in `text`, sorted.
+    pub fn positions(&mut self, text: &str) -> Vec<u32> {
+        self.indices.clear();
+
+        match &self.kind {
+            Kind::Fuzzy(pattern) => {
+                pattern.indices(
+                    Utf32Str::new(text, &mut self.buf),
+                    &mut self.matcher,
+                    &mut self.indices,
+                );
+            }
+            Kind::Atom(atom) => {
+                atom.indices(
+                    Utf32Str::new(text, &mut self.buf),
+                    &mut self.matcher,
+                    &mut self.indices,
+                );
+            }
+            Kind::Prefix { .. } | Kind::WordPrefix { .. } | Kind::Regex(_) => {
+                return self
+                    .plain_match(text)
+                    .map(|(_, positions)| positions)
+                    .unwrap_or_default();
+            }
+        }
+
+        self.indices.sort_unstable();
+        self.indices.dedup();
+        self.indices.clone()
+    }
+
+    /// Where the match starts and its char positions, for the modes nucleo
+    /// doesn't cover.
+    fn plain_match(&self, text: &str) -> Option<(u32, Vec<u32>)> {
+        match &self.kind {
+            Kind::Prefix {
+                prefix,
+                ignore_case,
+            } => {
+                let chars: Vec<char> = fold(text, *ignore_case).collect();
+
+                // like nucleo, leading whitespace is skipped unless the query has some
+                let start = if prefix.first().is_some_and(|c| c.is_whitespace()) {
+                    0
+                } else {
+                    chars.iter().take_while(|c| c.is_whitespace()).count()
+                };
+
+                chars[start..].starts_with(prefix).then(|| {
+                    let start = start as u32;
+                    (start, (start..start + prefix.len() as u32).collect())
+                })
+            }
+            Kind::WordPrefix { words, ignore_case } => {
+                let chars: Vec<char> = fold(text, *ignore_case).collect();
+                let mut positions = Vec::new();
+
+                for word in words {
+                    let start = word_starts(&chars).find(|&i| chars[i..].starts_with(word))?;
+                    positions.extend(start as u32..(start + word.len()) as u32);
+                }
+
+                positions.sort_unstable();
+                positions.dedup();
+
+                Some((positions.first().copied().unwrap_or_default(), positions))
+            }
+            Kind::Regex(regex) => {
+                let found = regex.as_ref()?.find(text)?;
+                let start = text[..found.start()].chars().count() as u32;
+                let len = found.as_str().chars().count() as u32;
+
+                Some((start, (start..start + len).collect()))
+            }
+            Kind::Fuzzy(_) | Kind::Atom(_) => None,
+        }
+    }
+}
+
+/// `text` as chars, lowercased one by one so positions stay the same.
+fn fold(text: &str, ignore_case: bool) -> impl Iterator<Item = char> + '_ {
+    text.chars().map(move |c| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    })
+}
+
+fn word_starts(chars: &[char]) -> impl Iterator<Item = usize> + '_ {
+    (0..chars.len())
+        .filter(|&i| chars[i].is_alphanumeric() && (i == 0 || !chars[i - 1].is_alphanumeric()))
+}
+
+/// Rank `texts` against `query`. Only matches are returned, in `order`. An
+/// empty query matches everything.
+pub fn rank<'a>(
+    query: &str,
+    mode: MatchMode,
+    case: CaseMode,
+    order: &Order,
+    texts: impl IntoIterator<Item = &'a str>,
+) -> Vec<Match> {
+    let mut matcher = LocalMatcher::new(query, mode, case).with_begin(order.needs_begin());
+    let min_score = matcher.min_score();
+
+    let mut matches: Vec<(usize, Score)> = texts
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, text)| matcher.matched(text).map(|score| (index, score)))
+        .filter(|(_, score)| score.score >= min_score)
+        .collect();
+
+    matches.sort_by(|(a_index, a), (b_index, b)| {
+        order.compare(a, *a_index as i64, b, *b_index as i64)
+    });
+
+    matches
+        .into_iter()
+        .map(|(index, score)| Match {
+            index,
+            score: score.score,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTS: [&str; 5] = ["walker", "the walker", "sidewalk", "Walking", "elephant"];
+
+    /// The texts of `TEXTS` matching `query`, in input order.
+    fn matching(query: &str, mode: MatchMode, case: CaseMode) -> Vec<&'static str> {
+        let mut indices: Vec<usize> = rank(query, mode, case, &Order::default(), TEXTS)
+            .into_iter()
+            .map(|m| m.index)
+            .collect();
+
+        indices.sort_unstable();
+        indices.into_iter().map(|i| TEXTS[i]).collect()
+    }
+
+    #[test]
+    fn modes() {
+        use CaseMode::Ignore;
+
+        assert_eq!(
+            matching("walk", MatchMode::Fuzzy, Ignore),
+            ["walker", "the walker", "sidewalk", "Walking"]
+        );
+        assert_eq!(
+            matching("wlkr", MatchMode::Fuzzy, Ignore),
+            ["walker", "the walker"]
+        );
+        assert_eq!(
+            matching("walk", MatchMode::Exact, Ignore),
+            ["walker", "the walker", "sidewalk", "Walking"]
+        );
+        assert!(matching("wlkr", MatchMode::Exact, Ignore).is_empty());
+        assert_eq!(
+            matching("walk", MatchMode::Prefix, Ignore),
+            ["walker", "Walking"]
+        );
+        assert_eq!(
+            matching("walk", MatchMode::WordPrefix, Ignore),
+            ["walker", "the walker", "Walking"]
+        );
+        assert_eq!(
+            matching("th wal", MatchMode::WordPrefix, Ignore),
+            ["the walker"]
+        );
+        assert_eq!(matching("^w.*r$", MatchMode::Regex, Ignore), ["walker"]);
+        assert_eq!(matching("", MatchMode::Regex, Ignore), TEXTS);
+    }
+
+    #[test]
+    fn invalid_regex_matches_nothing() {
+        assert!(matching("walk(", MatchMode::Regex, CaseMode::Ignore).is_empty());
+
+        let mut matcher = LocalMatcher::new("walk(", MatchMode::Regex, CaseMode::Ignore);
+        assert_eq!(matcher.score("walk("), None);
+        assert!(matcher.positions("walk(").is_empty());
+    }
+
+    #[test]
+    fn case_modes() {
+        for mode in MatchMode::ALL {
+            assert_eq!(
+                matching("Walk", mode, CaseMode::Smart),
+                ["Walking"],
+                "{mode:?}"
+            );
+            assert_eq!(
+                matching("walk", mode, CaseMode::Respect),
+                matching("walk", mode, CaseMode::Smart)
+                    .into_iter()
+                    .filter(|t| *t != "Walking")
+                    .collect::<Vec<_>>(),
+                "{mode:?}"
+            );
+            assert!(
+                matching("walk", mode, CaseMode::Smart).contains(&"Walking"),
+                "{mode:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn positions_are_char_indices() {
+        let mut matcher = LocalMatcher::new("walk", MatchMode::Regex, CaseMode::Ignore);
+        assert_eq!(matcher.positions("äwalk"), [1, 2, 3, 4]);
+
+        let mut matcher = LocalMatcher::new("Walk", MatchMode::Prefix, CaseMode::Respect);
+        assert_eq!(matcher.positions("  Walker"), [2, 3, 4, 5]);
+        assert!(matcher.positions("walker").is_empty());
+
+        let mut matcher = LocalMatcher::new("th wal", MatchMode::WordPrefix, CaseMode::Ignore);
+        assert_eq!(matcher.positions("the walker"), [0, 1, 4, 5, 6]);
+    }
+
+    #[test]
+    fn fuzzy_min_score_counts_chars() {
+        let matcher = LocalMatcher::new("äö", MatchMode::Fuzzy, CaseMode::Ignore);
+        assert_eq!(matcher.min_score(), 36);
+
+        let matcher = LocalMatcher::new("äö", MatchMode::Exact, CaseMode::Ignore);
+        assert_eq!(matcher.min_score(), 0);
+    }
+
+    /// A match scoring `score` that begins at `begin` in a text `len` chars long.
+    fn score(score: u32, begin: u32, len: u32) -> Score {
+        Score { score, begin, len }
+    }
+
+    /// A sorting order with `tiebreak`.
+    fn order(tiebreak: &[Tiebreak]) -> Order {
+        Order {
+            no_sort: false,
+            tiebreak: tiebreak.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse_tiebreaks() {
+        assert_eq!(
+            Tiebreak::parse_list("length, begin,index"),
+            Ok(vec![Tiebreak::Length, Tiebreak::Begin, Tiebreak::Index])
+        );
+        assert!(Tiebreak::parse_list("score").is_err());
+        assert!(Tiebreak::parse_list("length,").is_err());
+    }
+
+    #[test]
+    fn tiebreak_order() {
+        use Ordering::{Greater, Less};
+
+        let short = score(10, 5, 4);
+        let early = score(10, 0, 8);
+
+        // the score decides first, then the tiebreaks in turn, then the index
+        assert_eq!(order(&[]).compare(&score(11, 5, 9), 1, &short, 0), Less);
+        assert_eq!(order(&[]).compare(&short, 1, &early, 0), Greater);
+        assert_eq!(
+            order(&[Tiebreak::Length]).compare(&short, 1, &early, 0),
+            Less
+        );
+        assert_eq!(
+            order(&[Tiebreak::Begin]).compare(&short, 0, &early, 1),
+            Greater
+        );
+
+        let both = [Tiebreak::Length, Tiebreak::Begin];
+        assert_eq!(order(&both).compare(&short, 1, &early, 0), Less);
+        assert_eq!(
+            order(&both).compare(&score(10, 2, 4), 0, &score(10, 1, 4), 1),
+            Greater
+        );
+        assert_eq!(
+            order(&[Tiebreak::Index, Tiebreak::Length]).compare(&short, 1, &early, 0),
+            Greater
+        );
+
+        let no_sort = Order {
+            no_sort: true,
+            tiebreak: vec![Tiebreak::Length],
+        };
+        assert_eq!(no_sort.compare(&score(11, 0, 1), 1, &short, 0), Greater);
+        assert!(!no_sort.needs_begin());
+        assert!(order(&both).needs_begin());
+    }
+
+    #[test]
+    fn rank_order() {
+        let texts = ["walkers", "walk", "a walk", "talk"];
+        let ranked = |order: &Order| -> Vec<usize> {
+            rank("walk", MatchMode::Regex, CaseMode::Ignore, order, texts)
+                .into_iter()
+                .map(|m| m.index)
+                .collect()
+        };
+
+        assert_eq!(ranked(&order(&[])), [0, 1, 2]);
+        assert_eq!(ranked(&order(&[Tiebreak::Length])), [1, 0, 2]);
+
+        let no_sort = Order {
+            no_sort: true,
+            tiebreak: Vec::new(),
+        };
+        let texts = ["a walk", "walk"];
+        let ranked = rank("walk", MatchMode::Regex, CaseMode::Ignore, &no_sort, texts);
+        assert_eq!(ranked.iter().map(|m| m.index).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn parse_modes() {
+        for mode in MatchMode::ALL {
+            assert_eq!(MatchMode::parse(mode.name()), Some(mode));
+        }
+
+        assert_eq!(MatchMode::parse("word-prefix"), Some(MatchMode::WordPrefix));
+        assert_eq!(MatchMode::parse("substring"), None);
+        assert_eq!(MatchMode::Regex.next(), MatchMode::Fuzzy);
+        assert_eq!(CaseMode::parse("smart"), Some(CaseMode::Smart));
+        assert_eq!(CaseMode::parse("Smart"), None);
+    }
+}
@@ -9,6 +9,7 @@ use gtk4::{Box as GtkBox, Builder};
 use std::cell::RefCell;
 
 use super::PreviewWidget;
+use super::loaders::clear_command_cache;
 
 /// Thread-local preview handler.
 ///
@@ -83,7 +84,8 @@ impl UnifiedPreviewHandler {
                     widget.preview_file(&item.preview)
                 }
             }
-            "command" => widget.preview_command(&item.preview),
+            // dmenu commands only depend on the line, so their output can be reused
+            "command" => widget.preview_command(&item.preview, item.provider == "dmenu"),
             _ => return,
         };
 
@@ -121,4 +123,5 @@ pub fn clear_all_caches() {
     }
 
     PREVIEW_HANDLER.with(|h| h.borrow().clear_cache());
+    clear_command_cache();
 }
@@ -1,5 +1,7 @@
 //! File and command previews.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::Path;
 use std::process::Command;
 
@@ -8,6 +10,15 @@ use gtk4::{PolicyType, ScrolledWindow, TextView, WrapMode, prelude::*};
 use crate::preview::PreviewWidget;
 use crate::renderers::create_drag_source;
 
+thread_local! {
+    /// Output of cached preview commands, by command.
+    static COMMAND_OUTPUT: RefCell<HashMap<String, String>> = RefCell::default();
+}
+
+pub fn clear_command_cache() {
+    COMMAND_OUTPUT.with(|c| c.borrow_mut().clear());
+}
+
 impl PreviewWidget {
     pub fn preview_file(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         self.current_content = format!("file{}", file_path);
@@ -35,18 +46,42 @@ impl PreviewWidget {
         }
     }
 
-    pub fn preview_command(&mut self, command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    /// Show the output of `command`. With `cached`, the output is only computed
+    /// once until the caches are cleared.
+    pub fn preview_command(
+        &mut self,
+        command: &str,
+        cached: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         self.current_content = format!("command{}", command);
         self.clear_preview();
 
-        let output = Command::new("sh").arg("-c").arg(command).output()?;
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-
-        let combined = if stderr.is_empty() {
-            stdout.to_string()
+        let cached_output = if cached {
+            COMMAND_OUTPUT.with(|c| c.borrow().get(command).cloned())
         } else {
-            format!("{}\n\nSTDERR:\n{}", stdout, stderr)
+            None
+        };
+
+        let combined = match cached_output {
+            Some(output) => output,
+            None => {
+                let output = Command::new("sh").arg("-c").arg(command).output()?;
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+
+                let combined = if stderr.is_empty() {
+                    stdout.to_string()
+                } else {
+                    format!("{}\n\nSTDERR:\n{}", stdout, stderr)
+                };
+
+                if cached {
+                    COMMAND_OUTPUT
+                        .with(|c| c.borrow_mut().insert(command.to_string(), combined.clone()));
+                }
+
+                combined
+            }
         };
 
         let view = TextView::new();
@@ -0,0 +1,690 @@
+//! Framing for the elephant socket protocol.
+//!
+//! Requests are sent as `[kind, 0, len(4, BE)]` followed by the protobuf payload.
+//! Responses arrive as `[kind, len(4, BE)]` followed by the protobuf payload.
+//! Frames without a payload carry a length of 0.
+//!
+//! Every connection starts with a hello exchange, in which elephant reports its
+//! protocol version and installed providers, and agrees to the optional
+//! [`Features`] walker offers. Elephant versions from before the exchange don't
+//! answer it, and are spoken to in the original wire format without any of them.
+
+use std::fmt;
+use std::io;
+
+use protobuf::Message;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::protos::generated_proto::activate::ActivateRequest;
+use crate::protos::generated_proto::hello::{HelloRequest, HelloResponse};
+use crate::protos::generated_proto::providerstate::{ProviderStateRequest, ProviderStateResponse};
+use crate::protos::generated_proto::query::{QueryRequest, QueryResponse};
+use crate::protos::generated_proto::subscribe::{SubscribeRequest, SubscribeResponse};
+
+/// Protocol version walker speaks. Elephant has to report the same version.
+pub const PROTOCOL_VERSION: i32 = 1;
+
+pub const REQUEST_HEADER_LEN: usize = 6;
+pub const RESPONSE_HEADER_LEN: usize = 5;
+
+/// Upper bound for a single payload. Anything above is treated as a corrupt stream.
+pub const MAX_PAYLOAD_LEN: usize = 16 * 1024 * 1024;
+
+pub const CAPABILITY_SUBSCRIPTION_IDS: &str = "subscription-ids";
+pub const CAPABILITY_SUBSCRIPTION_ITEMS: &str = "subscription-items";
+
+/// Optional parts of the protocol. Walker offers all of them in the hello
+/// request, elephant answers with the ones it supports. Anything it leaves out
+/// keeps the original wire format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Subscription updates share the query connection as
+    /// [`ResponseKind::Subscription`] frames tagged with their `sid`. Otherwise
+    /// every subscription needs its own connection, on which updates arrive as
+    /// [`ResponseKind::Item`] frames.
+    pub subscription_ids: bool,
+    /// Subscription updates may carry a changed item, which replaces the shown
+    /// one in place. Otherwise every update reloads the results.
+    pub subscription_items: bool,
+}
+
+impl Features {
+    /// The capabilities walker offers.
+    pub fn offered() -> Vec<String> {
+        vec![
+            CAPABILITY_SUBSCRIPTION_IDS.to_string(),
+            CAPABILITY_SUBSCRIPTION_ITEMS.to_string(),
+        ]
+    }
+
+    /// The features elephant agreed to.
+    pub fn from_capabilities(capabilities: &[String]) -> Self {
+        let has = |capability: &str| capabilities.iter().any(|c| c == capability);
+
+        Self {
+            subscription_ids: has(CAPABILITY_SUBSCRIPTION_IDS),
+            subscription_items: has(CAPABILITY_SUBSCRIPTION_ITEMS),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// The peer closed the connection between two frames.
+    Closed,
+    /// The peer closed the connection in the middle of a frame.
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    /// The announced payload length exceeds `MAX_PAYLOAD_LEN`.
+    Oversized(usize),
+    /// The frame kind is not part of the protocol. The frame has been consumed.
+    UnknownKind(u8),
+    /// The payload is not a valid protobuf message. The frame has been consumed.
+    Decode(protobuf::Error),
+    Io(io::Error),
+}
+
+impl FrameError {
+    /// Whether the stream is still in sync after this error, i.e. the next
+    /// read starts at a frame boundary.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, FrameError::UnknownKind(_) | FrameError::Decode(_))
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Closed => write!(f, "connection closed"),
+            FrameError::Truncated { expected, actual } => {
+                write!(
+                    f,
+                    "truncated frame: expected {expected} bytes, got {actual}"
+                )
+            }
+            FrameError::Oversized(len) => write!(
+                f,
+                "oversized frame: {len} bytes exceeds limit of {MAX_PAYLOAD_LEN}"
+            ),
+            FrameError::UnknownKind(kind) => write!(f, "unknown frame kind: {kind}"),
+            FrameError::Decode(e) => write!(f, "invalid payload: {e}"),
+            FrameError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+impl From<protobuf::Error> for FrameError {
+    fn from(e: protobuf::Error) -> Self {
+        FrameError::Decode(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RequestKind {
+    Query = 0,
+    Activate = 1,
+    Subscribe = 2,
+    ProviderState = 4,
+    Hello = 6,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    Query(QueryRequest),
+    Activate(ActivateRequest),
+    Subscribe(SubscribeRequest),
+    ProviderState(ProviderStateRequest),
+    Hello(HelloRequest),
+}
+
+impl Request {
+    pub fn kind(&self) -> RequestKind {
+        match self {
+            Request::Query(_) => RequestKind::Query,
+            Request::Activate(_) => RequestKind::Activate,
+            Request::Subscribe(_) => RequestKind::Subscribe,
+            Request::ProviderState(_) => RequestKind::ProviderState,
+            Request::Hello(_) => RequestKind::Hello,
+        }
+    }
+
+    /// Encode header and payload into a single buffer.
+    pub fn encode(&self) -> Result<Vec<u8>, FrameError> {
+        let payload = match self {
+            Request::Query(req) => req.write_to_bytes()?,
+            Request::Activate(req) => req.write_to_bytes()?,
+            Request::Subscribe(req) => req.write_to_bytes()?,
+            Request::ProviderState(req) => req.write_to_bytes()?,
+            Request::Hello(req) => req.write_to_bytes()?,
+        };
+
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(FrameError::Oversized(payload.len()));
+        }
+
+        let mut buffer = Vec::with_capacity(REQUEST_HEADER_LEN + payload.len());
+        buffer.push(self.kind() as u8);
+        buffer.push(0);
+        buffer.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&payload);
+
+        Ok(buffer)
+    }
+}
+
+pub async fn write_request<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    req: &Request,
+) -> Result<(), FrameError> {
+    writer.write_all(&req.encode()?).await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ResponseKind {
+    /// A query result to append.
+    Item = 0,
+    /// A query result replacing the item with the same identifier.
+    ItemUpdate = 1,
+    /// An asynchronous action finished.
+    ActionDone = 2,
+    ProviderState = 3,
+    /// An update for the subscription with the `sid` in the payload, see
+    /// [`Features::subscription_ids`].
+    Subscription = 5,
+    /// The answer to the hello request.
+    Hello = 6,
+    /// Elephant checking that the connection is still alive, needs no answer.
+    HealthCheck = 230,
+    /// The query matched nothing.
+    NoResults = 253,
+    /// The current result list is stale and should be cleared.
+    Clear = 254,
+    /// All results for the current query have been sent.
+    Done = 255,
+}
+
+impl TryFrom<u8> for ResponseKind {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ResponseKind::Item),
+            1 => Ok(ResponseKind::ItemUpdate),
+            2 => Ok(ResponseKind::ActionDone),
+            3 => Ok(ResponseKind::ProviderState),
+            5 => Ok(ResponseKind::Subscription),
+            6 => Ok(ResponseKind::Hello),
+            230 => Ok(ResponseKind::HealthCheck),
+            253 => Ok(ResponseKind::NoResults),
+            254 => Ok(ResponseKind::Clear),
+            255 => Ok(ResponseKind::Done),
+            kind => Err(FrameError::UnknownKind(kind)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Item(QueryResponse),
+    ItemUpdate(QueryResponse),
+    ActionDone,
+    ProviderState(ProviderStateResponse),
+    Subscription(SubscribeResponse),
+    Hello(HelloResponse),
+    HealthCheck,
+    NoResults,
+    Clear,
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseHeader {
+    pub kind: u8,
+    pub len: usize,
+}
+
+/// Parse a response header, rejecting oversized payloads before any allocation.
+pub fn parse_response_header(
+    header: [u8; RESPONSE_HEADER_LEN],
+) -> Result<ResponseHeader, FrameError> {
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+
+    if len > MAX_PAYLOAD_LEN {
+        return Err(FrameError::Oversized(len));
+    }
+
+    Ok(ResponseHeader {
+        kind: header[0],
+        len,
+    })
+}
+
+pub fn decode_response(kind: u8, payload: &[u8]) -> Result<Response, FrameError> {
+    let response = match ResponseKind::try_from(kind)? {
+        ResponseKind::Item => Response::Item(QueryResponse::parse_from_bytes(payload)?),
+        ResponseKind::ItemUpdate => Response::ItemUpdate(QueryResponse::parse_from_bytes(payload)?),
+        ResponseKind::ActionDone => Response::ActionDone,
+        ResponseKind::ProviderState => {
+            Response::ProviderState(ProviderStateResponse::parse_from_bytes(payload)?)
+        }
+        ResponseKind::Subscription => {
+            Response::Subscription(SubscribeResponse::parse_from_bytes(payload)?)
+        }
+        ResponseKind::Hello => Response::Hello(HelloResponse::parse_from_bytes(payload)?),
+        ResponseKind::HealthCheck => Response::HealthCheck,
+        ResponseKind::NoResults => Response::NoResults,
+        ResponseKind::Clear => Response::Clear,
+        ResponseKind::Done => Response::Done,
+    };
+
+    Ok(response)
+}
+
+/// Read one raw frame. Always consumes the full frame, so decode errors don't
+/// desynchronize the stream.
+///
+/// Not cancel safe: dropping the future mid-frame loses the bytes read so far.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(u8, Vec<u8>), FrameError> {
+    let mut header = [0u8; RESPONSE_HEADER_LEN];
+
+    match read_full(reader, &mut header).await? {
+        0 => return Err(FrameError::Closed),
+        n if n < RESPONSE_HEADER_LEN => {
+            return Err(FrameError::Truncated {
+                expected: RESPONSE_HEADER_LEN,
+                actual: n,
+            });
+        }
+        _ => {}
+    }
+
+    let header = parse_response_header(header)?;
+
+    let mut payload = vec![0u8; header.len];
+    let n = read_full(reader, &mut payload).await?;
+
+    if n < header.len {
+        return Err(FrameError::Truncated {
+            expected: header.len,
+            actual: n,
+        });
+    }
+
+    Ok((header.kind, payload))
+}
+
+pub async fn read_response<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Response, FrameError> {
+    let (kind, payload) = read_frame(reader).await?;
+    decode_response(kind, &payload)
+}
+
+/// Send the hello request and wait for elephant's answer. Must be the first
+/// exchange on a new connection.
+///
+/// `None` if elephant closed the connection or answered with anything else,
+/// like versions from before the exchange do. The connection is unusable then.
+pub async fn hello<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<Option<HelloResponse>, FrameError> {
+    let mut req = HelloRequest::new();
+    req.version = PROTOCOL_VERSION;
+    req.capabilities = Features::offered();
+
+    write_request(stream, &Request::Hello(req)).await?;
+
+    match read_response(stream).await {
+        Ok(Response::Hello(resp)) => Ok(Some(resp)),
+        Ok(_) | Err(FrameError::Closed | FrameError::UnknownKind(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read the next update on a connection that carries a single subscription,
+/// for elephant versions without [`Features::subscription_ids`]. Other frames,
+/// like health checks, are skipped.
+pub async fn read_subscription_update<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<SubscribeResponse, FrameError> {
+    loop {
+        let (kind, payload) = read_frame(reader).await?;
+
+        if kind == ResponseKind::Item as u8 {
+            return Ok(SubscribeResponse::parse_from_bytes(&payload)?);
+        }
+    }
+}
+
+/// Like `read_exact`, but reports how many bytes were read before EOF.
+async fn read_full<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> Result<usize, FrameError> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]).await {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::protos::generated_proto::query::query_response::Item;
+
+    /// Elephant's side of the request framing.
+    fn decode_request(frame: &[u8]) -> Request {
+        let len = u32::from_be_bytes(frame[2..REQUEST_HEADER_LEN].try_into().unwrap()) as usize;
+        let payload = &frame[REQUEST_HEADER_LEN..];
+
+        assert_eq!(frame[1], 0);
+        assert_eq!(payload.len(), len);
+
+        match frame[0] {
+            0 => Request::Query(QueryRequest::parse_from_bytes(payload).unwrap()),
+            1 => Request::Activate(ActivateRequest::parse_from_bytes(payload).unwrap()),
+            2 => Request::Subscribe(SubscribeRequest::parse_from_bytes(payload).unwrap()),
+            4 => Request::ProviderState(ProviderStateRequest::parse_from_bytes(payload).unwrap()),
+            6 => Request::Hello(HelloRequest::parse_from_bytes(payload).unwrap()),
+            kind => panic!("unknown request kind {kind}"),
+        }
+    }
+
+    /// Elephant's side of the response framing.
+    fn encode_response(resp: &Response) -> Vec<u8> {
+        let (kind, payload) = match resp {
+            Response::Item(resp) => (ResponseKind::Item, resp.write_to_bytes().unwrap()),
+            Response::ItemUpdate(resp) => {
+                (ResponseKind::ItemUpdate, resp.write_to_bytes().unwrap())
+            }
+            Response::ActionDone => (ResponseKind::ActionDone, Vec::new()),
+            Response::ProviderState(resp) => {
+                (ResponseKind::ProviderState, resp.write_to_bytes().unwrap())
+            }
+            Response::Subscription(resp) => {
+                (ResponseKind::Subscription, resp.write_to_bytes().unwrap())
+            }
+            Response::Hello(resp) => (ResponseKind::Hello, resp.write_to_bytes().unwrap()),
+            Response::HealthCheck => (ResponseKind::HealthCheck, Vec::new()),
+            Response::NoResults => (ResponseKind::NoResults, Vec::new()),
+            Response::Clear => (ResponseKind::Clear, Vec::new()),
+            Response::Done => (ResponseKind::Done, Vec::new()),
+        };
+
+        frame(kind as u8, &payload)
+    }
+
+    fn frame(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = vec![kind];
+        buffer.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(payload);
+        buffer
+    }
+
+    fn item_response(identifier: &str, qid: i32) -> QueryResponse {
+        let mut item = Item::new();
+        item.identifier = identifier.to_string();
+        item.text = format!("text of {identifier}");
+        item.provider = "files".to_string();
+
+        let mut resp = QueryResponse::new();
+        resp.query = "query".to_string();
+        resp.item = protobuf::MessageField::some(item);
+        resp.qid = qid;
+        resp
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let mut query = QueryRequest::new();
+        query.providers = vec!["files".to_string(), "calc".to_string()];
+        query.query = "walker".to_string();
+        query.maxresults = 50;
+        query.exactsearch = true;
+        query.qid = 3;
+
+        let mut activate = ActivateRequest::new();
+        activate.provider = "files".to_string();
+        activate.identifier = "/tmp/walker".to_string();
+        activate.action = "open".to_string();
+        activate.arguments = "--new-window".to_string();
+        activate.single = true;
+
+        let mut subscribe = SubscribeRequest::new();
+        subscribe.provider = "clipboard".to_string();
+        subscribe.interval = 500;
+        subscribe.sid = 2;
+
+        let mut state = ProviderStateRequest::new();
+        state.provider = "bluetooth".to_string();
+
+        let mut hello = HelloRequest::new();
+        hello.version = PROTOCOL_VERSION;
+        hello.capabilities = Features::offered();
+
+        let requests = [
+            (Request::Query(query), RequestKind::Query),
+            (Request::Activate(activate), RequestKind::Activate),
+            (Request::Subscribe(subscribe), RequestKind::Subscribe),
+            (Request::ProviderState(state), RequestKind::ProviderState),
+            (Request::Hello(hello), RequestKind::Hello),
+        ];
+
+        for (req, kind) in requests {
+            let frame = req.encode().unwrap();
+
+            assert_eq!(req.kind(), kind);
+            assert_eq!(frame[0], kind as u8);
+            assert_eq!(decode_request(&frame), req);
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_round_trip() {
+        let mut state = ProviderStateResponse::new();
+        state.provider = "bluetooth".to_string();
+        state.states = vec!["powered".to_string()];
+        state.actions = vec!["find".to_string()];
+
+        let mut subscription = SubscribeResponse::new();
+        subscription.sid = 2;
+        subscription.value = "bluetooth:find".to_string();
+
+        let mut hello = HelloResponse::new();
+        hello.version = PROTOCOL_VERSION;
+        hello.providers = vec!["files".to_string(), "calc".to_string()];
+        hello.capabilities = Features::offered();
+
+        let responses = [
+            Response::Item(item_response("a", 1)),
+            Response::ItemUpdate(item_response("b", 2)),
+            Response::ActionDone,
+            Response::ProviderState(state),
+            Response::Subscription(subscription),
+            Response::Hello(hello),
+            Response::HealthCheck,
+            Response::NoResults,
+            Response::Clear,
+            Response::Done,
+        ];
+
+        let stream: Vec<u8> = responses.iter().flat_map(encode_response).collect();
+        let mut reader = stream.as_slice();
+
+        for resp in responses {
+            assert_eq!(read_response(&mut reader).await.unwrap(), resp);
+        }
+
+        assert!(matches!(
+            read_response(&mut reader).await,
+            Err(FrameError::Closed)
+        ));
+    }
+
+    #[test]
+    fn response_kinds_round_trip() {
+        let kinds = [
+            ResponseKind::Item,
+            ResponseKind::ItemUpdate,
+            ResponseKind::ActionDone,
+            ResponseKind::ProviderState,
+            ResponseKind::Subscription,
+            ResponseKind::Hello,
+            ResponseKind::HealthCheck,
+            ResponseKind::NoResults,
+            ResponseKind::Clear,
+            ResponseKind::Done,
+        ];
+
+        for kind in kinds {
+            assert_eq!(ResponseKind::try_from(kind as u8).unwrap(), kind);
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated_header() {
+        let stream = frame(ResponseKind::Done as u8, &[]);
+        let mut reader = &stream[..3];
+
+        assert!(matches!(
+            read_response(&mut reader).await,
+            Err(FrameError::Truncated {
+                expected: RESPONSE_HEADER_LEN,
+                actual: 3
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn truncated_payload() {
+        let stream = encode_response(&Response::Item(item_response("a", 1)));
+        let expected = stream.len() - RESPONSE_HEADER_LEN;
+        let mut reader = &stream[..stream.len() - 4];
+
+        let err = read_response(&mut reader).await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            FrameError::Truncated { expected: e, actual: a } if e == expected && a == expected - 4
+        ));
+        assert!(!err.is_recoverable());
+    }
+
+    #[tokio::test]
+    async fn oversized_response() {
+        let mut stream = vec![ResponseKind::Item as u8];
+        stream.extend_from_slice(&(MAX_PAYLOAD_LEN as u32 + 1).to_be_bytes());
+        let mut reader = stream.as_slice();
+
+        let err = read_response(&mut reader).await.unwrap_err();
+
+        assert!(matches!(err, FrameError::Oversized(len) if len == MAX_PAYLOAD_LEN + 1));
+        assert!(!err.is_recoverable());
+    }
+
+    #[test]
+    fn oversized_request() {
+        let mut query = QueryRequest::new();
+        query.query = "a".repeat(MAX_PAYLOAD_LEN + 1);
+
+        assert!(matches!(
+            Request::Query(query).encode(),
+            Err(FrameError::Oversized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unknown_kind_consumes_frame() {
+        let mut stream = frame(7, b"payload");
+        stream.extend(encode_response(&Response::Done));
+        let mut reader = stream.as_slice();
+
+        let err = read_response(&mut reader).await.unwrap_err();
+
+        assert!(matches!(err, FrameError::UnknownKind(7)));
+        assert!(err.is_recoverable());
+        assert_eq!(read_response(&mut reader).await.unwrap(), Response::Done);
+    }
+
+    #[test]
+    fn features_from_capabilities() {
+        assert_eq!(
+            Features::from_capabilities(&Features::offered()),
+            Features {
+                subscription_ids: true,
+                subscription_items: true,
+            }
+        );
+
+        assert_eq!(
+            Features::from_capabilities(&["unknown".to_string()]),
+            Features::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn subscription_updates_skip_other_frames() {
+        let mut update = SubscribeResponse::new();
+        update.value = "bluetooth:find".to_string();
+
+        let mut stream = encode_response(&Response::HealthCheck);
+        stream.extend(frame(
+            ResponseKind::Item as u8,
+            &update.write_to_bytes().unwrap(),
+        ));
+        let mut reader = stream.as_slice();
+
+        assert_eq!(read_subscription_update(&mut reader).await.unwrap(), update);
+        assert!(matches!(
+            read_subscription_update(&mut reader).await,
+            Err(FrameError::Closed)
+        ));
+    }
+
+    /// Answers `hello` as elephant would, with `reply` already waiting.
+    async fn greet(reply: Vec<u8>) -> Result<Option<HelloResponse>, FrameError> {
+        let (mut walker, mut elephant) = tokio::io::duplex(1024);
+        elephant.write_all(&reply).await.unwrap();
+        elephant.shutdown().await.unwrap();
+
+        hello(&mut walker).await
+    }
+
+    #[tokio::test]
+    async fn hello_answered() {
+        let mut resp = HelloResponse::new();
+        resp.version = PROTOCOL_VERSION;
+        resp.providers = vec!["files".to_string()];
+
+        let reply = encode_response(&Response::Hello(resp.clone()));
+
+        assert_eq!(greet(reply).await.unwrap(), Some(resp));
+    }
+
+    #[tokio::test]
+    async fn hello_unanswered() {
+        assert_eq!(greet(Vec::new()).await.unwrap(), None);
+        assert_eq!(greet(encode_response(&Response::Done)).await.unwrap(), None);
+        assert_eq!(greet(frame(7, &[])).await.unwrap(), None);
+    }
+}
@@ -1,4 +1,5 @@
 use gtk4::glib::{self, subclass::types::ObjectSubclassIsExt};
+use gtk4::prelude::{ObjectExt, WidgetExt};
 
 pub mod generated_proto {
     include!(concat!(env!("OUT_DIR"), "/generated_proto/mod.rs"));
@@ -15,7 +16,10 @@ mod imp {
     #[derive(Debug, Default)]
     pub struct QueryResponseObject {
         pub response: RefCell<Option<QueryResponse>>,
-        pub dmenu_score: RefCell<u32>,
+        pub index: RefCell<u32>,
+        pub marked: RefCell<bool>,
+        /// The row currently showing the object.
+        pub row: RefCell<glib::WeakRef<gtk4::Widget>>,
     }
 
     #[glib::object_subclass]
@@ -42,11 +46,73 @@ impl QueryResponseObject {
         self.imp().response.borrow().as_ref().unwrap().clone()
     }
 
-    pub fn dmenu_score(&self) -> u32 {
-        *self.imp().dmenu_score.borrow()
+    /// Position in the window's `ListStore`, which is never reordered.
+    pub fn index(&self) -> u32 {
+        *self.imp().index.borrow()
     }
 
-    pub fn set_dmenu_score(&self, val: u32) {
-        *self.imp().dmenu_score.borrow_mut() = val;
+    pub fn set_index(&self, val: u32) {
+        *self.imp().index.borrow_mut() = val;
+    }
+
+    /// The item's score, without cloning the response.
+    pub fn item_score(&self) -> i32 {
+        self.imp()
+            .response
+            .borrow()
+            .as_ref()
+            .and_then(|r| r.item.as_ref())
+            .map(|i| i.score)
+            .unwrap_or_default()
+    }
+
+    /// The item's text, without cloning the rest of the response.
+    pub fn text(&self) -> String {
+        self.imp()
+            .response
+            .borrow()
+            .as_ref()
+            .and_then(|r| r.item.as_ref())
+            .map(|i| i.text.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn marked(&self) -> bool {
+        *self.imp().marked.borrow()
+    }
+
+    /// Mark the object and set the `marked` css class on its row, if it's shown.
+    pub fn set_marked(&self, val: bool) {
+        *self.imp().marked.borrow_mut() = val;
+
+        if let Some(row) = self.imp().row.borrow().upgrade() {
+            if val {
+                row.add_css_class("marked");
+            } else {
+                row.remove_css_class("marked");
+            }
+        }
+    }
+
+    /// Remember the row showing the object, for `set_marked`.
+    pub fn set_row(&self, row: Option<&gtk4::Widget>) {
+        self.imp()
+            .row
+            .replace(row.map(ObjectExt::downgrade).unwrap_or_default());
+    }
+
+    /// Stores locally computed match positions as `fuzzyinfo` on the text field,
+    /// so locally filtered lists get the same highlighting as elephant results.
+    pub fn set_fuzzy_positions(&self, positions: Vec<i32>) {
+        if let Some(response) = self.imp().response.borrow_mut().as_mut() {
+            let info = response
+                .item
+                .mut_or_insert_default()
+                .fuzzyinfo
+                .mut_or_insert_default();
+            info.field = "text".to_string();
+            info.start = positions.first().copied().unwrap_or_default();
+            info.positions = positions;
+        }
     }
 }
@@ -1,7 +1,9 @@
 use chrono::DateTime;
 
 use crate::{
-    config::get_config, protos::generated_proto::query::query_response::Item, providers::Provider,
+    config::get_config,
+    protos::generated_proto::query::query_response::Item,
+    providers::{Provider, char_offset, set_highlighted_part, set_highlighted_text},
 };
 
 #[derive(Debug)]
@@ -27,35 +29,38 @@ impl Provider for Clipboard {
     fn text_transformer(&self, item: &Item, label: &gtk4::Label) {
         if item.preview_type == "file" {
             let Ok(dt) = DateTime::parse_from_rfc2822(&item.subtext) else {
-                label.set_label(&item.subtext);
+                set_highlighted_text(label, item, "subtext", &item.subtext);
                 return;
             };
 
             let formatted = dt
                 .format(&get_config().providers.clipboard.time_format)
                 .to_string();
-            label.set_label(&formatted);
+            label.set_text(&formatted);
 
             return;
         }
 
-        label.set_label(item.text.trim());
+        let text = item.text.trim();
+        let offset = char_offset(&item.text, item.text.len() - item.text.trim_start().len());
+
+        set_highlighted_part(label, item, "text", text, offset);
     }
 
     fn subtext_transformer(&self, item: &Item, label: &gtk4::Label) {
         if item.preview_type == "file" {
-            label.set_label("Image");
+            label.set_text("Image");
             return;
         }
 
         let Ok(dt) = DateTime::parse_from_rfc2822(&item.subtext) else {
-            label.set_label(&item.subtext);
+            set_highlighted_text(label, item, "subtext", &item.subtext);
             return;
         };
 
         let formatted = dt
             .format(&get_config().providers.clipboard.time_format)
             .to_string();
-        label.set_label(&formatted);
+        label.set_text(&formatted);
     }
 }
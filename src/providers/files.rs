@@ -6,7 +6,10 @@ use gtk4::{
     gio::{self, prelude::FileExt},
 };
 
-use crate::{protos::generated_proto::query::query_response::Item, providers::Provider};
+use crate::{
+    protos::generated_proto::query::query_response::Item,
+    providers::{Provider, char_offset, set_highlighted_part},
+};
 
 #[derive(Debug)]
 pub struct Files {
@@ -34,24 +37,28 @@ impl Provider for Files {
             .and_then(|f| f.to_str())
             .unwrap();
 
-        label.set_text(text);
+        let offset = char_offset(&item.text, item.text.rfind(text).unwrap_or_default());
+
+        set_highlighted_part(label, item, "text", text, offset);
     }
 
     fn subtext_transformer(&self, item: &Item, label: &Label) {
-        let subtext = Path::new(&item.text)
+        // the parent folder starts the path, a home folder shown as `~` shifts it
+        let (subtext, offset) = Path::new(&item.text)
             .parent()
             .and_then(|p| p.to_str())
             .map(|parent_folder| {
                 if let Ok(home) = env::var("HOME") {
                     if let Some(stripped) = parent_folder.strip_prefix(&home) {
-                        return format!("~{}", stripped);
+                        let offset = home.chars().count().saturating_sub(1);
+                        return (format!("~{}", stripped), offset);
                     }
                 }
-                parent_folder.to_string()
+                (parent_folder.to_string(), 0)
             })
             .unwrap_or_default();
 
-        label.set_text(&subtext);
+        set_highlighted_part(label, item, "text", &subtext, offset);
     }
 
     fn image_transformer(&self, b: &Builder, _: &ListItem, item: &Item) {
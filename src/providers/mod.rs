@@ -2,8 +2,7 @@ use std::{
     collections::{HashMap, HashSet},
     fmt::Debug,
     path::Path,
-    process::Command,
-    sync::OnceLock,
+    sync::{Arc, LazyLock, RwLock},
 };
 
 use gtk4::{
@@ -15,6 +14,7 @@ use gtk4::{
 
 use crate::{
     config::get_config,
+    data::fetch_providers,
     keybinds::Action,
     protos::generated_proto::query::query_response::Item,
     providers::{
@@ -140,7 +140,7 @@ pub trait Provider: Sync + Send + Debug {
             return;
         }
 
-        label.set_text(&item.text);
+        set_highlighted_text(label, item, "text", &item.text);
     }
 
     fn subtext_transformer(&self, item: &Item, label: &Label) {
@@ -149,7 +149,7 @@ pub trait Provider: Sync + Send + Debug {
             return;
         }
 
-        label.set_text(&item.subtext);
+        set_highlighted_text(label, item, "subtext", &item.subtext);
     }
 
     fn image_transformer(&self, b: &Builder, i: &ListItem, item: &Item) {
@@ -157,6 +157,70 @@ pub trait Provider: Sync + Send + Debug {
     }
 }
 
+/// Sets `text` on `label`, highlighting the characters the fuzzy matcher picked
+/// if `field` is the field named in the item's `fuzzyinfo`.
+pub fn set_highlighted_text(label: &Label, item: &Item, field: &str, text: &str) {
+    set_highlighted_part(label, item, field, text, 0);
+}
+
+/// Like [`set_highlighted_text`], for labels showing only the part of `field`
+/// that starts at char `offset`, f.e. the file name out of a path. Positions
+/// outside of it aren't shown.
+pub fn set_highlighted_part(label: &Label, item: &Item, field: &str, text: &str, offset: usize) {
+    let markup = &get_config().highlight_markup;
+
+    let positions: Vec<i32> = match item.fuzzyinfo.as_ref() {
+        // `FuzzyInfo.positions` (query.proto) are char indices into `field`
+        Some(info) if !markup.is_empty() && info.field == field => info
+            .positions
+            .iter()
+            .filter_map(|p| (*p as usize).checked_sub(offset))
+            .map(|p| p as i32)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    if positions.is_empty() {
+        label.set_text(text);
+    } else {
+        label.set_markup(&highlight_markup(text, &positions, markup));
+    }
+}
+
+/// Char count of `text` up to the byte index `byte`, for [`set_highlighted_part`].
+pub fn char_offset(text: &str, byte: usize) -> usize {
+    text.get(..byte).map_or(0, |t| t.chars().count())
+}
+
+/// Escapes `text` for pango and wraps every run of matched characters in `markup`,
+/// where `{}` stands for the matched run. Positions are character indices.
+pub fn highlight_markup(text: &str, positions: &[i32], markup: &str) -> String {
+    let positions: HashSet<usize> = positions.iter().map(|p| *p as usize).collect();
+
+    let mut result = String::new();
+    let mut run = String::new();
+
+    for (i, c) in text.chars().enumerate() {
+        if positions.contains(&i) {
+            run.push(c);
+            continue;
+        }
+
+        if !run.is_empty() {
+            result.push_str(&markup.replace("{}", &glib::markup_escape_text(&run)));
+            run.clear();
+        }
+
+        result.push_str(&glib::markup_escape_text(c.encode_utf8(&mut [0; 4])));
+    }
+
+    if !run.is_empty() {
+        result.push_str(&markup.replace("{}", &glib::markup_escape_text(&run)));
+    }
+
+    result
+}
+
 pub fn shared_image_transformer(b: &Builder, _: &ListItem, item: &Item) {
     let mut is_text = false;
 
@@ -221,44 +285,64 @@ pub fn shared_image_transformer(b: &Builder, _: &ListItem, item: &Item) {
     }
 }
 
-pub static PROVIDERS: OnceLock<HashMap<String, Box<dyn Provider>>> = OnceLock::new();
+static PROVIDERS: LazyLock<RwLock<Arc<HashMap<String, Box<dyn Provider>>>>> =
+    LazyLock::new(RwLock::default);
+
+/// Providers that are part of walker itself and always registered.
+const BUILTIN_PROVIDERS: [&str; 3] = ["dmenu", "actionmenu", "emergency"];
+
+/// Snapshot of the provider registry. It can be replaced at any time, so don't
+/// hold on to it longer than needed.
+pub fn get_providers() -> Arc<HashMap<String, Box<dyn Provider>>> {
+    PROVIDERS.read().unwrap().clone()
+}
+
+/// Names of the installed elephant providers, from the config or elephant itself.
+/// Asking elephant blocks, so the UI uses [`known_providers`] instead.
+pub fn installed_providers(elephant: bool) -> Vec<String> {
+    let config = get_config();
+
+    if let Some(val) = &config.installed_providers {
+        val.clone()
+    } else if elephant {
+        fetch_providers().unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Names of the installed elephant providers from the config, or the registered
+/// ones. Doesn't ask elephant, `data::refresh_providers` does that.
+pub fn known_providers() -> Vec<String> {
+    match &get_config().installed_providers {
+        Some(val) => val.clone(),
+        None => get_providers()
+            .keys()
+            .filter(|k| !BUILTIN_PROVIDERS.contains(&k.as_str()))
+            .cloned()
+            .collect(),
+    }
+}
 
-pub fn setup_providers(elephant: bool) {
+/// Whether the registry holds exactly the given elephant providers.
+pub fn has_providers(provider_list: &[String]) -> bool {
+    let providers = get_providers();
+
+    let registered: HashSet<&str> = providers
+        .keys()
+        .map(String::as_str)
+        .filter(|k| !BUILTIN_PROVIDERS.contains(k))
+        .collect();
+
+    registered == provider_list.iter().map(String::as_str).collect()
+}
+
+pub fn setup_providers(provider_list: Vec<String>) {
     let mut providers: HashMap<String, Box<dyn Provider>> = HashMap::new();
     providers.insert("dmenu".to_string(), Box::new(Dmenu::new()));
     providers.insert("actionmenu".to_string(), Box::new(ActionsMenu::new()));
     providers.insert("emergency".to_string(), Box::new(Emergency::new()));
 
-    let provider_list: Vec<String> = {
-        let config = get_config();
-
-        if let Some(val) = &config.installed_providers {
-            val.clone()
-        } else if elephant {
-            match Command::new("elephant").arg("listproviders").output() {
-                Ok(output) => match String::from_utf8(output.stdout) {
-                    Ok(stdout) => stdout
-                        .lines()
-                        .filter_map(|line| line.split_once(';').map(|(_, value)| value.to_string()))
-                        .collect(),
-                    Err(e) => {
-                        eprintln!("Error parsing elephant output as UTF-8: {}", e);
-                        Vec::new()
-                    }
-                },
-                Err(e) => {
-                    eprintln!(
-                        "Error running 'elephant' command: {}. Make sure it is installed.",
-                        e
-                    );
-                    Vec::new()
-                }
-            }
-        } else {
-            Vec::new()
-        }
-    };
-
     provider_list.into_iter().for_each(|p| {
         match p.as_str() {
             "calc" => providers.insert("calc".to_string(), Box::new(Calc::new())),
@@ -285,7 +369,5 @@ pub fn setup_providers(elephant: bool) {
         };
     });
 
-    PROVIDERS
-        .set(providers)
-        .expect("couldn't initialize providers.")
+    *PROVIDERS.write().unwrap() = Arc::new(providers);
 }
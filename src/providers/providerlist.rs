@@ -1,5 +1,7 @@
 use crate::{
-    config::get_config, protos::generated_proto::query::query_response::Item, providers::Provider,
+    config::get_config,
+    protos::generated_proto::query::query_response::Item,
+    providers::{Provider, set_highlighted_text},
 };
 
 #[derive(Debug)]
@@ -30,6 +32,11 @@ impl Provider for Providerlist {
             .find(|p| p.provider == item.identifier)
         {
             label.set_text(format!("( {} )", &prefix.prefix).as_str());
+            return;
+        }
+
+        if !item.subtext.is_empty() {
+            set_highlighted_text(label, item, "subtext", &item.subtext);
         }
     }
 
@@ -1,6 +1,6 @@
 use crate::config::get_config;
 use crate::protos::generated_proto::query::query_response::Item;
-use crate::providers::PROVIDERS;
+use crate::providers::get_providers;
 use crate::state::{get_dmenu_current, is_grid, is_hide_qa, set_error};
 use crate::theme::{Theme, with_themes};
 use crate::ui::item::drag::create_drag_source;
@@ -20,11 +20,18 @@ use std::path::Path;
 ///
 /// Error handling:
 /// - If a theme is broken (missing 'ItemBox'), it will fall back to default theme
+/// - Items of providers that aren't registered anymore are left empty
 ///
 /// TODO:
 /// - Validate XML structure earlier (theme load time)
 /// - Avoid rebuilding Builder on fallback
 pub fn create_item(list_item: &ListItem, item: &Item, theme: &Theme) {
+    // results can outlive their provider, f.e. when it was uninstalled on a reload
+    let providers = get_providers();
+    let Some(provider) = providers.get(&item.provider) else {
+        return;
+    };
+
     let mut builder = Builder::new();
 
     // Load the correct XML layout
@@ -88,8 +95,6 @@ pub fn create_item(list_item: &ListItem, item: &Item, theme: &Theme) {
         itembox.add_controller(create_drag_source(&item.text));
     }
 
-    let provider = PROVIDERS.get().unwrap().get(&item.provider).unwrap();
-
     // Apply provider text transformations
     if let Some(label) = builder.object::<Label>("ItemText") {
         provider.text_transformer(item, &label);
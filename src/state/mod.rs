@@ -1,8 +1,12 @@
 use std::collections::HashSet;
 use std::sync::{OnceLock, RwLock};
 
-use crate::data::get_provider_state;
+use crate::config::get_config;
+use crate::data::{ConnectionState, get_provider_state};
+use crate::dmenu::{AcceptKey, Fields};
 use crate::keybinds::AfterAction;
+use crate::matching::{CaseMode, MatchMode, Order};
+use crate::protocol::Features;
 use crate::protos::generated_proto::providerstate::ProviderStateResponse;
 use crate::protos::generated_proto::query::QueryResponse;
 use crate::ui::window::{
@@ -19,8 +23,8 @@ pub struct AppState {
     hide_qa: bool,
     has_elephant: bool,
     stay_open_explicit_provider: bool,
-    is_connected: bool,
-    is_connecting: bool,
+    connection: ConnectionState,
+    features: Features,
     dmenu_keep_open: bool,
     dmenu_exit_after: bool,
     dmenu_current: i64,
@@ -36,6 +40,8 @@ pub struct AppState {
     parameter_min_width: Option<i32>,
     parameter_max_height: Option<i32>,
     parameter_max_width: Option<i32>,
+    parameter_query: Option<String>,
+    select_query: bool,
     last_query: String,
     placeholder: String,
     initial_placeholder: String,
@@ -49,6 +55,14 @@ pub struct AppState {
     no_hints: bool,
     input_only: bool,
     index: bool,
+    multi: bool,
+    password: bool,
+    match_mode: Option<MatchMode>,
+    match_case: Option<CaseMode>,
+    dmenu_order: Order,
+    dmenu_delimiter: Option<String>,
+    dmenu_accept_nth: Option<Fields>,
+    dmenu_accept_keys: Vec<AcceptKey>,
     is_dmenu: bool,
     is_actions_menu: bool,
     action_menu_item: QueryResponse,
@@ -166,6 +180,111 @@ pub fn set_placeholder(val: String) {
     STATE.get().unwrap().write().unwrap().placeholder = val
 }
 
+pub fn get_parameter_query() -> Option<String> {
+    STATE.get().unwrap().read().unwrap().parameter_query.clone()
+}
+
+pub fn set_parameter_query(val: Option<String>) {
+    STATE.get().unwrap().write().unwrap().parameter_query = val
+}
+
+pub fn is_select_query() -> bool {
+    STATE.get().unwrap().read().unwrap().select_query
+}
+
+pub fn set_select_query(val: bool) {
+    STATE.get().unwrap().write().unwrap().select_query = val
+}
+
+pub fn is_multi() -> bool {
+    STATE.get().unwrap().read().unwrap().multi
+}
+
+pub fn set_multi(val: bool) {
+    STATE.get().unwrap().write().unwrap().multi = val
+}
+
+pub fn is_password() -> bool {
+    STATE.get().unwrap().read().unwrap().password
+}
+
+pub fn set_password(val: bool) {
+    STATE.get().unwrap().write().unwrap().password = val
+}
+
+/// The match mode for local lists, `--match` or the config's default.
+pub fn get_match_mode() -> MatchMode {
+    STATE
+        .get()
+        .unwrap()
+        .read()
+        .unwrap()
+        .match_mode
+        .unwrap_or_else(|| get_config().match_mode)
+}
+
+pub fn set_match_mode(val: Option<MatchMode>) {
+    STATE.get().unwrap().write().unwrap().match_mode = val
+}
+
+pub fn get_match_case() -> CaseMode {
+    STATE
+        .get()
+        .unwrap()
+        .read()
+        .unwrap()
+        .match_case
+        .unwrap_or_else(|| get_config().match_case)
+}
+
+pub fn set_match_case(val: Option<CaseMode>) {
+    STATE.get().unwrap().write().unwrap().match_case = val
+}
+
+pub fn get_dmenu_order() -> Order {
+    STATE.get().unwrap().read().unwrap().dmenu_order.clone()
+}
+
+pub fn set_dmenu_order(val: Order) {
+    STATE.get().unwrap().write().unwrap().dmenu_order = val
+}
+
+pub fn get_dmenu_delimiter() -> Option<String> {
+    STATE.get().unwrap().read().unwrap().dmenu_delimiter.clone()
+}
+
+pub fn set_dmenu_delimiter(val: Option<String>) {
+    STATE.get().unwrap().write().unwrap().dmenu_delimiter = val
+}
+
+pub fn get_dmenu_accept_nth() -> Option<Fields> {
+    STATE
+        .get()
+        .unwrap()
+        .read()
+        .unwrap()
+        .dmenu_accept_nth
+        .clone()
+}
+
+pub fn set_dmenu_accept_nth(val: Option<Fields>) {
+    STATE.get().unwrap().write().unwrap().dmenu_accept_nth = val
+}
+
+pub fn get_dmenu_accept_keys() -> Vec<AcceptKey> {
+    STATE
+        .get()
+        .unwrap()
+        .read()
+        .unwrap()
+        .dmenu_accept_keys
+        .clone()
+}
+
+pub fn set_dmenu_accept_keys(val: Vec<AcceptKey>) {
+    STATE.get().unwrap().write().unwrap().dmenu_accept_keys = val
+}
+
 pub fn get_error() -> String {
     STATE.get().unwrap().read().unwrap().error.clone()
 }
@@ -211,10 +330,24 @@ pub fn set_has_elephant(val: bool) {
 }
 
 pub fn is_connected() -> bool {
-    STATE.get().unwrap().read().unwrap().is_connected
+    get_connection_state() == ConnectionState::Connected
+}
+
+pub fn get_connection_state() -> ConnectionState {
+    STATE.get().unwrap().read().unwrap().connection
 }
-pub fn set_is_connected(val: bool) {
-    STATE.get().unwrap().write().unwrap().is_connected = val
+
+pub fn set_connection_state(val: ConnectionState) {
+    STATE.get().unwrap().write().unwrap().connection = val
+}
+
+/// The protocol features the connected elephant agreed to.
+pub fn get_features() -> Features {
+    STATE.get().unwrap().read().unwrap().features
+}
+
+pub fn set_features(val: Features) {
+    STATE.get().unwrap().write().unwrap().features = val
 }
 
 pub fn is_stay_open_explicit_provider() -> bool {
@@ -235,14 +368,6 @@ pub fn set_is_stay_open_explicit_provider(val: bool) {
         .stay_open_explicit_provider = val
 }
 
-pub fn is_connecting() -> bool {
-    STATE.get().unwrap().read().unwrap().is_connecting
-}
-
-pub fn set_is_connecting(val: bool) {
-    STATE.get().unwrap().write().unwrap().is_connecting = val
-}
-
 pub fn is_input_only() -> bool {
     STATE.get().unwrap().read().unwrap().input_only
 }
@@ -0,0 +1,241 @@
+//! Elephant subscriptions.
+//!
+//! Each subscription gets an id (`sid`), which routes its updates to the
+//! handler registered for it. Elephant versions that agree to
+//! `Features::subscription_ids` echo it on the shared query connection, for
+//! all others every subscription gets its own connection and the id is filled
+//! in from that.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+
+use gtk4::{glib, prelude::*};
+
+use crate::QueryResponseObject;
+use crate::config::get_config;
+use crate::data::send_subscription;
+use crate::protos::generated_proto::query::query_response::Item;
+use crate::protos::generated_proto::subscribe::{SubscribeRequest, SubscribeResponse};
+use crate::providers::get_providers;
+use crate::state::{
+    get_features, get_prefix_provider, get_provider, is_visible, set_global_provider_actions,
+    set_is_visible, set_provider,
+};
+use crate::ui::window::{set_input_text, set_keybind_hint, with_window};
+
+type Handler = Arc<dyn Fn(SubscribeResponse) + Send + Sync>;
+
+struct Subscription {
+    request: SubscribeRequest,
+    handler: Handler,
+}
+
+static SUBSCRIPTIONS: LazyLock<RwLock<HashMap<i32, Subscription>>> = LazyLock::new(RwLock::default);
+static NEXT_SID: AtomicI32 = AtomicI32::new(1);
+
+/// Register a subscription and return its id.
+///
+/// The request is sent right away if elephant is connected, and again after
+/// every reconnect. The handler is called on the connection thread.
+pub fn subscribe<F>(provider: &str, interval: i32, query: &str, handler: F) -> i32
+where
+    F: Fn(SubscribeResponse) + Send + Sync + 'static,
+{
+    let sid = NEXT_SID.fetch_add(1, Ordering::Relaxed);
+
+    let mut req = SubscribeRequest::new();
+    req.sid = sid;
+    req.provider = provider.to_string();
+    req.interval = interval;
+    req.query = query.to_string();
+
+    SUBSCRIPTIONS.write().unwrap().insert(
+        sid,
+        Subscription {
+            request: req.clone(),
+            handler: Arc::new(handler),
+        },
+    );
+
+    send_subscription(req);
+
+    sid
+}
+
+/// Send all registered subscriptions, f.e. after (re)connecting.
+pub fn resubscribe() {
+    let requests: Vec<SubscribeRequest> = SUBSCRIPTIONS
+        .read()
+        .unwrap()
+        .values()
+        .map(|s| s.request.clone())
+        .collect();
+
+    requests.into_iter().for_each(send_subscription);
+}
+
+/// Route a subscription response to its handler.
+pub fn dispatch(resp: SubscribeResponse) {
+    let handler = SUBSCRIPTIONS
+        .read()
+        .unwrap()
+        .get(&resp.sid)
+        .map(|s| s.handler.clone());
+
+    match handler {
+        Some(handler) => handler(resp),
+        None => eprintln!("no handler for subscription {}", resp.sid),
+    }
+}
+
+/// Register the built-in and configured subscriptions. Must run after `setup_providers`.
+/// Running it again only adds subscriptions for newly installed providers, and
+/// drops the ones of uninstalled providers, so they aren't sent again.
+pub fn setup_subscriptions() {
+    let providers = get_providers();
+
+    SUBSCRIPTIONS.write().unwrap().retain(|_, s| {
+        s.request.provider == "menus" || providers.contains_key(&s.request.provider)
+    });
+
+    if !is_subscribed("menus", 0, "") {
+        subscribe("menus", 0, "", handle_menus);
+    }
+
+    if providers.contains_key("bluetooth") && !is_subscribed("bluetooth", 0, "") {
+        subscribe("bluetooth", 0, "", handle_bluetooth);
+    }
+
+    get_config()
+        .providers
+        .subscriptions
+        .iter()
+        .filter(|(provider, sub)| {
+            providers.contains_key(*provider) && !is_subscribed(provider, sub.interval, &sub.query)
+        })
+        .for_each(|(provider, sub)| {
+            let name = provider.clone();
+
+            subscribe(provider, sub.interval, &sub.query, move |resp| {
+                handle_provider_update(name.clone(), resp)
+            });
+        });
+}
+
+fn is_subscribed(provider: &str, interval: i32, query: &str) -> bool {
+    SUBSCRIPTIONS.read().unwrap().values().any(|s| {
+        s.request.provider == provider && s.request.interval == interval && s.request.query == query
+    })
+}
+
+/// A response carrying an item replaces the shown item with the same identifier,
+/// if elephant agreed to `Features::subscription_items`. Any other response
+/// reloads the list if the provider's results are shown.
+fn handle_provider_update(provider: String, resp: SubscribeResponse) {
+    glib::idle_add_once(move || {
+        if !is_visible() {
+            return;
+        }
+
+        let item = resp
+            .item
+            .into_option()
+            .filter(|_| get_features().subscription_items);
+
+        match item {
+            Some(item) => update_item(item),
+            None if is_showing(&provider) => with_window(|w| {
+                if let Some(input) = &w.input {
+                    input.emit_by_name::<()>("changed", &[]);
+                }
+            }),
+            None => (),
+        }
+    });
+}
+
+fn is_showing(provider: &str) -> bool {
+    if get_provider() == provider || get_prefix_provider() == provider {
+        return true;
+    }
+
+    with_window(|w| {
+        w.items
+            .iter::<glib::Object>()
+            .flatten()
+            .filter_map(|obj| obj.downcast::<QueryResponseObject>().ok())
+            .any(|obj| {
+                obj.response()
+                    .item
+                    .as_ref()
+                    .is_some_and(|i| i.provider == provider)
+            })
+    })
+}
+
+fn update_item(item: Item) {
+    with_window(|w| {
+        let items = &w.items;
+
+        let Some((pos, obj)) = (0..items.n_items()).find_map(|pos| {
+            items
+                .item(pos)
+                .and_downcast::<QueryResponseObject>()
+                .filter(|obj| {
+                    obj.response().item.as_ref().is_some_and(|existing| {
+                        existing.provider == item.provider && existing.identifier == item.identifier
+                    })
+                })
+                .map(|obj| (pos, obj))
+        }) else {
+            return;
+        };
+
+        if item.text == "%DELETE%" {
+            items.remove(pos);
+        } else {
+            let mut resp = obj.response();
+            resp.item = protobuf::MessageField::some(item);
+            items.splice(pos, 1, &[QueryResponseObject::new(resp)]);
+        }
+
+        set_keybind_hint();
+    });
+}
+
+fn handle_menus(resp: SubscribeResponse) {
+    glib::idle_add_once(move || {
+        set_global_provider_actions(None);
+        set_provider(resp.value);
+
+        with_window(|w| {
+            set_input_text("");
+            w.window.present();
+        });
+
+        set_is_visible(true);
+    });
+}
+
+fn handle_bluetooth(resp: SubscribeResponse) {
+    glib::idle_add_once(move || {
+        with_window(|w| {
+            if let Some(p) = &w.placeholder {
+                match resp.value.as_str() {
+                    "bluetooth:remove" => p.set_text("Removing..."),
+                    "bluetooth:connect" => p.set_text("Connecting..."),
+                    "bluetooth:disconnect" => p.set_text("Disconnecting..."),
+                    "bluetooth:trust" => p.set_text("Trusting..."),
+                    "bluetooth:untrust" => p.set_text("Un-Trusting..."),
+                    "bluetooth:pair" => p.set_text("Pairing..."),
+                    "bluetooth:find" => p.set_text("Scanning..."),
+                    _ => (),
+                }
+
+                p.set_visible(true);
+                w.scroll.set_visible(false);
+            }
+        });
+    });
+}
@@ -1,6 +1,6 @@
 use super::Theme;
 use crate::config::get_config;
-use crate::providers::PROVIDERS;
+use crate::providers::get_providers;
 use crate::state::add_theme;
 use crate::theme::state::THEMES;
 
@@ -45,13 +45,7 @@ pub fn setup_themes(elephant: bool, theme: String, is_service: bool) {
 
     let files = if elephant {
         let mut combined = base_files;
-        combined.extend(
-            PROVIDERS
-                .get()
-                .unwrap()
-                .iter()
-                .map(|v| format!("item_{}.xml", v.0)),
-        );
+        combined.extend(get_providers().iter().map(|v| format!("item_{}.xml", v.0)));
         combined
     } else {
         base_files
@@ -98,7 +92,7 @@ pub fn setup_themes(elephant: bool, theme: String, is_service: bool) {
     }
 
     THEMES.with(|cell| {
-        cell.set(themes).expect("failed initializing themes");
+        cell.replace(Some(themes));
     });
 }
 
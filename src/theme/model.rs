@@ -1,4 +1,4 @@
-use crate::providers::PROVIDERS;
+use crate::providers::get_providers;
 use gtk4::gio;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -49,7 +49,7 @@ impl Theme {
         };
 
         // Populate provider item layouts
-        for (k, v) in PROVIDERS.get().unwrap() {
+        for (k, v) in get_providers().iter() {
             theme.items.insert(k.clone(), v.get_item_layout());
             theme.grid_items.insert(k.clone(), v.get_item_grid_layout());
         }
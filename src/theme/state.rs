@@ -1,12 +1,12 @@
 use super::Theme;
-use std::cell::OnceCell;
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 /// Global theme registry.
 ///
-/// Initialized once during startup via `setup_themes`.
+/// Initialized during startup via `setup_themes`, replaced when providers change.
 thread_local! {
-    pub static THEMES: OnceCell<HashMap<String, Theme>> = OnceCell::new();
+    pub static THEMES: RefCell<Option<HashMap<String, Theme>>> = const { RefCell::new(None) };
 }
 
 /// Access the loaded themes.
@@ -18,7 +18,7 @@ where
     F: FnOnce(&HashMap<String, Theme>) -> R,
 {
     THEMES.with(|state| {
-        let data = state.get().expect("Themes not initialized");
-        f(data)
+        let data = state.borrow();
+        f(data.as_ref().expect("Themes not initialized"))
     })
 }
@@ -0,0 +1,293 @@
+//! Filtering for lists that are matched locally instead of by elephant, like
+//! dmenu, the emergency list and the actions menu.
+//!
+//! The window's `ListStore` is never reordered, so an item's position in it is
+//! its stable index. Scores are kept in a table by that index, which the
+//! window's `FilterListModel` and `SortListModel` read. Large lists are scored
+//! in chunks on a worker thread. The previous results stay until the first chunk
+//! is done, after that the list is refreshed as chunks come in, at most once
+//! per `REFRESH_INTERVAL` and once more for the last chunk.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gtk4::gio::ListStore;
+use gtk4::glib::{self, object::CastNone};
+use gtk4::prelude::{FilterExt, ListModelExt, SorterExt, WidgetExt};
+use gtk4::{FilterChange, SorterChange};
+
+use crate::QueryResponseObject;
+use crate::matching::{CaseMode, LocalMatcher, MatchMode, Order, Score};
+use crate::state::{
+    get_dmenu_order, get_match_case, get_match_mode, get_query, is_actions_menu, is_dmenu,
+    is_emergency, set_match_mode,
+};
+use crate::ui::window::{WindowData, with_window};
+
+/// Lists up to this size are scored on the main thread, larger ones in chunks
+/// of this size on a worker.
+const CHUNK_SIZE: usize = 50_000;
+
+/// Refreshing filters and sorts the whole list, so chunks in between are only
+/// shown this often.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(150);
+
+pub fn is_local() -> bool {
+    is_dmenu() || is_emergency() || is_actions_menu()
+}
+
+#[derive(Default)]
+pub struct LocalFilter {
+    /// Item texts by index, shared with the worker.
+    texts: Arc<RwLock<Vec<String>>>,
+    /// Scores for the current query by index, `None` if the item didn't match.
+    scores: RefCell<Vec<Option<Score>>>,
+    query: RefCell<String>,
+    order: RefCell<Order>,
+    /// Matches scoring lower are hidden.
+    min_score: Cell<u32>,
+    /// Matcher for the current query, used for new items and highlighting.
+    matcher: RefCell<Option<LocalMatcher>>,
+    /// Bumped whenever running workers should stop.
+    generation: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for LocalFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalFilter")
+            .field("query", &self.query)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalFilter {
+    /// Keep texts, scores and indices in sync with the `ListStore`. Has to be
+    /// connected before any model on top of it, so they see the new scores.
+    pub fn items_changed(&self, items: &ListStore, pos: u32, removed: u32, added: u32) {
+        let added_items: Vec<QueryResponseObject> = (pos..pos + added)
+            .filter_map(|i| items.item(i).and_downcast::<QueryResponseObject>())
+            .collect();
+
+        let texts: Vec<String> = added_items.iter().map(QueryResponseObject::text).collect();
+
+        let scores: Vec<Option<Score>> = match self.matcher.borrow_mut().as_mut() {
+            Some(matcher) => texts.iter().map(|t| matcher.matched(t)).collect(),
+            None => vec![None; texts.len()],
+        };
+
+        let range = pos as usize..(pos + removed) as usize;
+        let shifted = removed != added && pos + added < items.n_items();
+
+        // running workers would write their results to the wrong items
+        if shifted || (removed > 0 && removed != added) {
+            self.generation.fetch_add(1, AtomicOrdering::Relaxed);
+        }
+
+        if shifted {
+            (pos..items.n_items())
+                .filter_map(|i| items.item(i).and_downcast::<QueryResponseObject>())
+                .zip(pos..)
+                .for_each(|(obj, i)| obj.set_index(i));
+        } else {
+            added_items
+                .iter()
+                .zip(pos..)
+                .for_each(|(obj, i)| obj.set_index(i));
+        }
+
+        self.texts.write().unwrap().splice(range.clone(), texts);
+        self.scores.borrow_mut().splice(range, scores);
+    }
+
+    pub fn is_visible(&self, obj: &QueryResponseObject) -> bool {
+        if self.query.borrow().is_empty() {
+            return true;
+        }
+
+        self.score(obj)
+            .is_some_and(|score| score.score >= self.min_score.get())
+    }
+
+    /// Matches first, in the current order. The items' own score stands in for
+    /// the input order, for dmenu it's derived from the line number.
+    pub fn compare(&self, a: &QueryResponseObject, b: &QueryResponseObject) -> Ordering {
+        let a_index = -i64::from(a.item_score());
+        let b_index = -i64::from(b.item_score());
+
+        match (self.score(a), self.score(b)) {
+            (Some(a), Some(b)) => self.order.borrow().compare(&a, a_index, &b, b_index),
+            (a, b) => b.is_some().cmp(&a.is_some()).then(a_index.cmp(&b_index)),
+        }
+    }
+
+    fn score(&self, obj: &QueryResponseObject) -> Option<Score> {
+        self.scores
+            .borrow()
+            .get(obj.index() as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Set the match positions on `obj` before it's rendered. Done here rather
+    /// than while scoring, so only visible items pay for them.
+    pub fn highlight(&self, obj: &QueryResponseObject) {
+        let positions = match self.matcher.borrow_mut().as_mut() {
+            Some(matcher) => matcher.positions(&obj.text()),
+            None => Vec::new(),
+        };
+
+        obj.set_fuzzy_positions(positions.into_iter().map(|i| i as i32).collect());
+    }
+
+    /// Switch to `query` and return the generation results for it belong to.
+    fn restart(&self, query: &str, mode: MatchMode, case: CaseMode, order: Order) -> u64 {
+        let matcher = (!query.is_empty())
+            .then(|| LocalMatcher::new(query, mode, case).with_begin(order.needs_begin()));
+
+        self.min_score
+            .set(matcher.as_ref().map_or(0, LocalMatcher::min_score));
+        self.query.replace(query.to_string());
+        self.matcher.replace(matcher);
+        self.order.replace(order);
+
+        self.generation.fetch_add(1, AtomicOrdering::Relaxed) + 1
+    }
+
+    fn score_all(&self) {
+        let texts = self.texts.read().unwrap();
+
+        let scores: Vec<Option<Score>> = match self.matcher.borrow_mut().as_mut() {
+            Some(matcher) => texts.iter().map(|t| matcher.matched(t)).collect(),
+            None => vec![None; texts.len()],
+        };
+
+        self.scores.replace(scores);
+    }
+
+    /// Store the worker's scores for `range`. The first chunk drops the previous
+    /// query's scores, so they don't mix with the new ones.
+    fn apply(&self, generation: u64, range: Range<usize>, scores: Vec<Option<Score>>) -> bool {
+        if self.generation.load(AtomicOrdering::Relaxed) != generation {
+            return false;
+        }
+
+        if range.start == 0 {
+            self.scores.replace(vec![None; self.len()]);
+        }
+
+        match self.scores.borrow_mut().get_mut(range) {
+            Some(slice) => {
+                slice.copy_from_slice(&scores);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.texts.read().unwrap().len()
+    }
+}
+
+/// Filter and sort the local list for `query`.
+pub fn filter_local_items(query: &str) {
+    with_window(|w| {
+        let local = &w.local_filter;
+        let (mode, case, order) = (get_match_mode(), get_match_case(), get_dmenu_order());
+        let begin = order.needs_begin();
+        let generation = local.restart(query, mode, case, order);
+        let large = local.len() > CHUNK_SIZE;
+
+        w.filter_model.set_incremental(large);
+        w.sort_model.set_incremental(large);
+
+        if query.is_empty() || !large {
+            local.score_all();
+            refresh(w);
+            return;
+        }
+
+        // the previous results stay until the worker's first chunk replaces them
+        score_in_background(
+            LocalMatcher::new(query, mode, case).with_begin(begin),
+            local.texts.clone(),
+            local.generation.clone(),
+            generation,
+        );
+    });
+}
+
+fn score_in_background(
+    mut matcher: LocalMatcher,
+    texts: Arc<RwLock<Vec<String>>>,
+    current: Arc<AtomicU64>,
+    generation: u64,
+) {
+    thread::spawn(move || {
+        let mut start = 0;
+        let mut refreshed: Option<Instant> = None;
+
+        while current.load(AtomicOrdering::Relaxed) == generation {
+            let (scores, last): (Vec<Option<Score>>, bool) = {
+                let texts = texts.read().unwrap();
+                let end = texts.len().min(start + CHUNK_SIZE);
+
+                let scores = texts
+                    .get(start..end)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|t| matcher.matched(t))
+                    .collect();
+
+                (scores, end == texts.len())
+            };
+
+            let range = start..start + scores.len();
+            start = range.end;
+
+            let show = last || refreshed.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL);
+            if show {
+                refreshed = Some(Instant::now());
+            }
+
+            glib::idle_add_once(move || {
+                with_window(|w| {
+                    if w.local_filter.apply(generation, range, scores) && show {
+                        refresh(w);
+                    }
+                });
+            });
+
+            if last {
+                break;
+            }
+        }
+    });
+}
+
+fn refresh(w: &WindowData) {
+    w.filter.changed(FilterChange::Different);
+    w.sorter.changed(SorterChange::Different);
+    w.selection.set_selected(0);
+}
+
+/// Switch to the next match mode and filter again.
+pub fn cycle_match_mode() {
+    set_match_mode(Some(get_match_mode().next()));
+    update_match_mode_class();
+    filter_local_items(&get_query());
+}
+
+/// Show the match mode as a css class on the window, f.e. `match-regex`.
+pub fn update_match_mode_class() {
+    with_window(|w| {
+        MatchMode::css_classes().for_each(|class| w.window.remove_css_class(&class));
+        w.window.add_css_class(&get_match_mode().css_class());
+    });
+}
@@ -1 +1,2 @@
+pub mod local_filter;
 pub mod window;
@@ -1,19 +1,26 @@
 use crate::{
     GLOBAL_DMENU_SENDER, QueryResponseObject,
     config::get_config,
-    data::{activate, input_changed, set_state},
+    data::{
+        ConnectionState, activate, dmenu_result, input_changed, print_dmenu_result, retry_now,
+        set_state,
+    },
+    dmenu::{AcceptKey, DmenuResult, accept_result, find_accept_key},
     keybinds::{
-        ACTION_CLOSE, ACTION_QUICK_ACTIVATE, ACTION_RESUME_LAST_QUERY, ACTION_SELECT_DOWN,
-        ACTION_SELECT_LEFT, ACTION_SELECT_NEXT, ACTION_SELECT_PAGE_DOWN, ACTION_SELECT_PAGE_UP,
-        ACTION_SELECT_PREVIOUS, ACTION_SELECT_RIGHT, ACTION_SELECT_UP, ACTION_SHOW_ACTIONS,
-        ACTION_TOGGLE_EXACT, Action, AfterAction, get_bind, get_fallback_action, get_provider_bind,
+        ACTION_CLOSE, ACTION_CYCLE_MATCH_MODE, ACTION_QUICK_ACTIVATE, ACTION_RESUME_LAST_QUERY,
+        ACTION_RETRY_CONNECTION, ACTION_SELECT_DOWN, ACTION_SELECT_LEFT, ACTION_SELECT_NEXT,
+        ACTION_SELECT_PAGE_DOWN, ACTION_SELECT_PAGE_UP, ACTION_SELECT_PREVIOUS,
+        ACTION_SELECT_RIGHT, ACTION_SELECT_UP, ACTION_SHOW_ACTIONS, ACTION_TOGGLE_EXACT,
+        ACTION_TOGGLE_MARK, Action, AfterAction, get_bind, get_fallback_action, get_provider_bind,
         get_provider_global_bind, get_show_actions_action,
     },
+    matching::Order,
+    protocol::PROTOCOL_VERSION,
     protos::generated_proto::query::{
         QueryResponse,
         query_response::{self, Item},
     },
-    providers::{PROVIDERS, Provider},
+    providers::{Provider, get_providers},
     renderers::create_item,
     send_message,
     state::{
@@ -22,24 +29,27 @@ use crate::{
         get_initial_max_width, get_initial_min_height, get_initial_min_width,
         get_initial_placeholder, get_initial_width, get_last_query, get_prefix_provider,
         get_provider, get_query, get_theme, is_actions_menu, is_connected, is_dmenu,
-        is_dmenu_exit_after, is_dmenu_keep_open, is_emergency, is_grid, is_no_hints, is_service,
-        set_action_menu_item, set_action_menu_prefix, set_action_menu_query, set_async_after,
-        set_current_prefix, set_current_set, set_dmenu_current, set_dmenu_exit_after,
-        set_dmenu_keep_open, set_error, set_hide_qa, set_index, set_initial_height,
-        set_initial_max_height, set_initial_max_width, set_initial_min_height,
+        is_dmenu_exit_after, is_dmenu_keep_open, is_emergency, is_grid, is_multi, is_no_hints,
+        is_password, set_action_menu_item, set_action_menu_prefix, set_action_menu_query,
+        set_async_after, set_current_prefix, set_current_set, set_dmenu_accept_keys,
+        set_dmenu_accept_nth, set_dmenu_current, set_dmenu_delimiter, set_dmenu_exit_after,
+        set_dmenu_keep_open, set_dmenu_order, set_error, set_hide_qa, set_index,
+        set_initial_height, set_initial_max_height, set_initial_max_width, set_initial_min_height,
         set_initial_min_width, set_initial_placeholder, set_initial_width, set_input_only,
         set_is_actions_menu, set_is_dmenu, set_is_grid, set_is_stay_open_explicit_provider,
-        set_is_visible, set_last_query, set_no_hints, set_no_search, set_param_close,
-        set_parameter_height, set_parameter_max_height, set_parameter_max_width,
-        set_parameter_min_height, set_parameter_min_width, set_parameter_width, set_placeholder,
-        set_provider, set_query, set_theme,
+        set_is_visible, set_last_query, set_match_case, set_match_mode, set_multi, set_no_hints,
+        set_no_search, set_param_close, set_parameter_height, set_parameter_max_height,
+        set_parameter_max_width, set_parameter_min_height, set_parameter_min_width,
+        set_parameter_query, set_parameter_width, set_password, set_placeholder, set_provider,
+        set_query, set_select_query, set_theme,
     },
     theme::{Theme, setup_layer_shell, with_themes},
+    ui::local_filter::{LocalFilter, cycle_match_mode, is_local},
 };
 use gtk4::{
-    Application, Builder, Button, CustomFilter, Entry, EventControllerKey, EventControllerMotion,
-    FilterListModel, GestureClick, Label, PropagationPhase, ScrolledWindow, SignalListItemFactory,
-    SingleSelection, Window,
+    Application, Builder, Button, CustomFilter, CustomSorter, Entry, EventControllerKey,
+    EventControllerMotion, FilterListModel, GestureClick, Label, PropagationPhase, ScrolledWindow,
+    SignalListItemFactory, SingleSelection, SortListModel, Window,
     prelude::{BoxExt, ButtonExt},
 };
 use gtk4::{Box, ListScrollFlags};
@@ -65,6 +75,7 @@ use std::{
     cell::{Cell, OnceCell, RefCell},
     collections::HashMap,
     process::{self, Command, Stdio},
+    rc::Rc,
 };
 
 thread_local! {
@@ -97,6 +108,11 @@ pub struct WindowData {
     pub list_max_columns: u32,
     pub input: Option<Entry>,
     pub items: ListStore,
+    pub local_filter: Rc<LocalFilter>,
+    pub filter: CustomFilter,
+    pub filter_model: FilterListModel,
+    pub sorter: CustomSorter,
+    pub sort_model: SortListModel,
     pub placeholder: Option<Label>,
     pub elephant_hint: Label,
     pub keybinds: gtk4::Box,
@@ -186,27 +202,42 @@ pub fn setup_theme_window(app: &Application, val: &Theme) -> Result<WindowData,
     let input: Option<Entry> = builder.object("Input");
     let placeholder: Option<Label> = builder.object("Placeholder");
 
+    let local_filter = Rc::new(LocalFilter::default());
+    let items = ListStore::new::<QueryResponseObject>();
+
+    // before the models are created, so the scores are up to date for them
+    items.connect_items_changed({
+        let local_filter = local_filter.clone();
+        move |items, pos, removed, added| local_filter.items_changed(items, pos, removed, added)
+    });
+
     let filter = CustomFilter::new({
+        let local_filter = local_filter.clone();
         move |entry| {
             let item = entry.downcast_ref::<QueryResponseObject>().unwrap();
 
-            let q = get_query();
-
-            if (is_actions_menu() || is_dmenu() || is_emergency()) && !q.is_empty() {
-                let f = 18 * q.len();
+            !is_local() || local_filter.is_visible(item)
+        }
+    });
 
-                if item.dmenu_score() < f as u32 {
-                    return false;
-                }
+    // elephant results are already sorted, equal items keep their order
+    let sorter = CustomSorter::new({
+        let local_filter = local_filter.clone();
+        move |a, b| {
+            if !is_local() {
+                return gtk4::Ordering::Equal;
             }
 
-            true
+            let a = a.downcast_ref::<QueryResponseObject>().unwrap();
+            let b = b.downcast_ref::<QueryResponseObject>().unwrap();
+
+            local_filter.compare(a, b).into()
         }
     });
 
-    let items = ListStore::new::<QueryResponseObject>();
     let filter_model = FilterListModel::new(Some(items.clone()), Some(filter.clone()));
-    let selection = SingleSelection::new(Some(filter_model.clone()));
+    let sort_model = SortListModel::new(Some(filter_model.clone()), Some(sorter.clone()));
+    let selection = SingleSelection::new(Some(sort_model.clone()));
     let search_container: Option<Box> = builder.object("SearchContainer");
     let preview_container: Option<Box> = builder.object("Preview");
     let max_columns = list.max_columns();
@@ -231,6 +262,11 @@ pub fn setup_theme_window(app: &Application, val: &Theme) -> Result<WindowData,
         list_max_columns: max_columns,
         input,
         items,
+        local_filter,
+        filter,
+        filter_model,
+        sorter,
+        sort_model,
         placeholder,
         keybinds,
         global_keybinds,
@@ -288,6 +324,57 @@ pub fn check_error() {
     });
 }
 
+/// Reflect the elephant connection in the `ElephantHint` label and as a
+/// `connection-*` CSS class on the window.
+pub fn show_connection_state(state: ConnectionState) {
+    with_window(|w| {
+        w.window
+            .css_classes()
+            .iter()
+            .filter(|c| c.starts_with("connection-"))
+            .for_each(|c| w.window.remove_css_class(c));
+
+        w.window.add_css_class(state.css_class());
+
+        match state {
+            ConnectionState::Connecting => {
+                w.elephant_hint.set_text("Connecting to elephant...");
+            }
+            ConnectionState::Retrying(secs) => {
+                w.elephant_hint
+                    .set_text(&format!("Waiting for elephant... retrying in {secs}s"));
+            }
+            ConnectionState::VersionMismatch(version) => {
+                w.elephant_hint.set_text(&format!(
+                    "elephant speaks protocol version {version}, walker requires {PROTOCOL_VERSION}. Please update."
+                ));
+            }
+            ConnectionState::Connected => {
+                w.elephant_hint.set_visible(false);
+                w.scroll.set_visible(true);
+                return;
+            }
+        }
+
+        if is_dmenu() || is_emergency() {
+            return;
+        }
+
+        if matches!(
+            state,
+            ConnectionState::Retrying(_) | ConnectionState::VersionMismatch(_)
+        ) {
+            w.elephant_hint.set_visible(true);
+            w.scroll.set_visible(false);
+            w.keybinds.set_visible(false);
+
+            if let Some(p) = &w.preview_container {
+                p.set_visible(false);
+            }
+        }
+    });
+}
+
 fn setup_window_behavior(ui: &WindowData, app: &Application) {
     if let Some(p) = &ui.placeholder {
         p.set_visible(false);
@@ -347,8 +434,10 @@ fn activate_default(app: &Application) {
         if let Some(item) = get_selected_item() {
             let provider = item.provider.clone();
 
-            let providers = PROVIDERS.get().unwrap();
-            let p = providers.get(&provider).unwrap();
+            let providers = get_providers();
+            let Some(p) = providers.get(&provider) else {
+                return;
+            };
 
             let actions = p.get_keybind_hint(&item.actions);
 
@@ -356,9 +445,12 @@ fn activate_default(app: &Application) {
                 actions
                     .iter()
                     .find(|a| a.action == *item.actions.first().unwrap())
-                    .unwrap()
             } else {
-                actions.iter().find(|a| a.default.unwrap_or(false)).unwrap()
+                actions.iter().find(|a| a.default.unwrap_or(false))
+            };
+
+            let Some(action) = action else {
+                return;
             };
 
             activate(get_selected_query_response(), &provider, &query, action);
@@ -393,24 +485,23 @@ fn setup_input_handling(input: &Entry) -> gdk::glib::SignalHandlerId {
     })
 }
 
-fn handle_dmenu_print(w: &WindowData) -> Option<AfterAction> {
-    let mut text = w
+/// Accept the selected item, or the typed text if nothing is selected.
+fn handle_dmenu_print(w: &WindowData, accepted_by: Option<&AcceptKey>) -> Option<AfterAction> {
+    let text = w
         .input
         .as_ref()
         .map(Entry::text)
         .unwrap_or_default()
         .to_string();
 
-    if text.is_empty() {
-        text = "CNCLD".to_string();
-    }
+    let mut values = dmenu_result(get_selected_item().as_ref());
 
-    if is_service() {
-        send_message(text);
-    } else {
-        println!("{text}");
+    if values.is_empty() && !text.is_empty() {
+        values.push(text);
     }
 
+    print_dmenu_result(accept_result(values, accepted_by));
+
     Some(AfterAction::Close)
 }
 
@@ -505,7 +596,7 @@ fn handle_provider(query: &str, k: gdk::Key, m: gdk::ModifierType) -> Option<Aft
 fn handle_actions_menu(selected: &Item) -> Option<AfterAction> {
     let response = get_action_menu_item();
     let item = response.item.as_ref()?;
-    let providers = PROVIDERS.get().unwrap();
+    let providers = get_providers();
 
     if let Some(p) = &providers.get(&item.provider) {
         let action = if let Some(action) = p
@@ -554,10 +645,12 @@ fn setup_keyboard_handling(ui: &WindowData) {
 
         let handled = with_window(|w| {
             if !is_connected() && !is_dmenu() {
-                if let Some(action) = get_bind(k, m, is_grid())
-                    && action.action == ACTION_CLOSE
-                {
-                    quit(&app, true);
+                if let Some(action) = get_bind(k, m, is_grid()) {
+                    match action.action.as_str() {
+                        ACTION_CLOSE => quit(&app, true),
+                        ACTION_RETRY_CONNECTION => retry_now(),
+                        _ => (),
+                    }
                 }
 
                 if !is_emergency() {
@@ -567,7 +660,11 @@ fn setup_keyboard_handling(ui: &WindowData) {
 
             let mut after: Option<AfterAction> = None;
 
-            if k == gdk::Key::Return {
+            if is_dmenu()
+                && let Some(accept) = find_accept_key(k, m)
+            {
+                after = handle_dmenu_print(w, Some(&accept));
+            } else if k == gdk::Key::Return {
                 if let Some(item) = get_selected_item() {
                     if is_emergency() {
                         after = handle_emergency(&item);
@@ -577,7 +674,7 @@ fn setup_keyboard_handling(ui: &WindowData) {
                         after = handle_actions_menu(&item);
                     }
                 } else if is_dmenu() {
-                    after = handle_dmenu_print(w);
+                    after = handle_dmenu_print(w, None);
                 }
             }
 
@@ -604,6 +701,11 @@ fn setup_keyboard_handling(ui: &WindowData) {
                         ACTION_SELECT_PAGE_DOWN => select_page_down(),
                         ACTION_SELECT_PAGE_UP => select_page_up(),
                         ACTION_SHOW_ACTIONS => show_actions_menu(get_selected_query_response()),
+                        ACTION_TOGGLE_MARK if is_dmenu() && is_multi() => toggle_mark(),
+                        // leave the key, f.e. Tab, to gtk outside of multi dmenu
+                        ACTION_TOGGLE_MARK => return false,
+                        ACTION_CYCLE_MATCH_MODE if is_local() => cycle_match_mode(),
+                        ACTION_CYCLE_MATCH_MODE => return false,
                         action if action.starts_with(ACTION_QUICK_ACTIVATE) => {
                             if let Some((_, after)) = action.split_once(":") {
                                 let i: u32 = after.parse().unwrap();
@@ -689,7 +791,9 @@ fn setup_list_behavior(ui: &WindowData) {
         item.set_child(None::<&gtk4::Widget>);
     });
 
-    factory.connect_bind(|_, item| {
+    let local_filter = ui.local_filter.clone();
+
+    factory.connect_bind(move |_, item| {
         let item = item
             .downcast_ref::<gtk4::ListItem>()
             .expect("failed casting to ListItem");
@@ -699,6 +803,10 @@ fn setup_list_behavior(ui: &WindowData) {
             .and_downcast_ref::<QueryResponseObject>()
             .expect("The item has to be a QueryResponseObject");
 
+        if is_local() {
+            local_filter.highlight(response_obj);
+        }
+
         let response = response_obj.response();
 
         with_themes(|t| {
@@ -710,6 +818,12 @@ fn setup_list_behavior(ui: &WindowData) {
                 }
             }
         });
+
+        response_obj.set_row(item.child().as_ref());
+
+        if response_obj.marked() {
+            response_obj.set_marked(true);
+        }
     });
 
     ui.list.set_model(Some(&ui.selection));
@@ -745,15 +859,15 @@ fn setup_mouse_handling(ui: &WindowData) {
 }
 
 fn reset_provider_states() {
-    if is_connected() && PROVIDERS.get().unwrap().contains_key("clipboard") {
+    if is_connected() && get_providers().contains_key("clipboard") {
         set_state("clipboard", "show_combined");
     }
 
-    if is_connected() && PROVIDERS.get().unwrap().contains_key("todo") {
+    if is_connected() && get_providers().contains_key("todo") {
         set_state("todo", "search");
     }
 
-    if is_connected() && PROVIDERS.get().unwrap().contains_key("bookmarks") {
+    if is_connected() && get_providers().contains_key("bookmarks") {
         set_state("bookmarks", "search");
     }
 }
@@ -761,8 +875,10 @@ fn reset_provider_states() {
 pub fn quit(app: &Application, cancelled: bool) {
     reset_provider_states();
 
+    let password = is_password();
+
     if GLOBAL_DMENU_SENDER.read().unwrap().is_some() {
-        send_message("CNCLD".to_string());
+        send_message(DmenuResult::default());
     }
 
     if !app
@@ -801,6 +917,8 @@ pub fn quit(app: &Application, cancelled: bool) {
     set_parameter_min_width(None);
     set_parameter_max_height(None);
     set_parameter_max_width(None);
+    set_parameter_query(None);
+    set_select_query(false);
     set_is_actions_menu(false);
     set_no_search(false);
     set_no_hints(false);
@@ -808,6 +926,14 @@ pub fn quit(app: &Application, cancelled: bool) {
     set_is_visible(false);
     set_dmenu_current(0);
     set_is_dmenu(false);
+    set_multi(false);
+    set_password(false);
+    set_match_mode(None);
+    set_match_case(None);
+    set_dmenu_order(Order::default());
+    set_dmenu_delimiter(None);
+    set_dmenu_accept_nth(None);
+    set_dmenu_accept_keys(Vec::new());
     set_input_only(false);
     set_param_close(false);
     set_hide_qa(false);
@@ -820,10 +946,19 @@ pub fn quit(app: &Application, cancelled: bool) {
         set_dmenu_keep_open(false);
     }
 
-    gtk4::glib::idle_add_once(|| {
+    gtk4::glib::idle_add_once(move || {
         with_window(|w| {
             if let Some(input) = &w.input {
-                set_last_query(input.text().to_string());
+                if password {
+                    // don't keep the secret around, not even in the entry
+                    let sid = w.sid.as_ref().unwrap();
+                    input.block_signal(sid);
+                    input.set_text("");
+                    input.unblock_signal(sid);
+                    input.set_visibility(true);
+                } else {
+                    set_last_query(input.text().to_string());
+                }
 
                 if !get_initial_placeholder().is_empty() {
                     input.set_placeholder_text(Some(&get_initial_placeholder()));
@@ -983,7 +1118,7 @@ fn quick_activate(app: &Application, i: u32) {
 }
 
 pub fn resume_last_query() {
-    if !get_last_query().is_empty() {
+    if !is_password() && !get_last_query().is_empty() {
         set_input_text(&get_last_query());
     }
 }
@@ -1015,6 +1150,58 @@ fn disable_mouse() {
     });
 }
 
+/// Toggle the mark on the selected item and move on to the next one.
+fn toggle_mark() {
+    with_window(|w| {
+        let Some(obj) = w
+            .selection
+            .selected_item()
+            .and_downcast::<QueryResponseObject>()
+        else {
+            return;
+        };
+
+        obj.set_marked(!obj.marked());
+    });
+
+    update_mark_counter();
+    select_next();
+}
+
+/// Marked items in input order.
+pub fn get_marked_items() -> Vec<Item> {
+    with_window(|w| {
+        let mut items: Vec<Item> = w
+            .items
+            .iter::<QueryResponseObject>()
+            .flatten()
+            .filter(QueryResponseObject::marked)
+            .filter_map(|obj| obj.response().item.into_option())
+            .collect();
+
+        items.sort_by_key(|i| std::cmp::Reverse(i.score));
+        items
+    })
+}
+
+pub fn update_mark_counter() {
+    with_window(|w| {
+        let Some(counter) = w.builder.object::<Label>("MarkCounter") else {
+            return;
+        };
+
+        let marked = w
+            .items
+            .iter::<QueryResponseObject>()
+            .flatten()
+            .filter(QueryResponseObject::marked)
+            .count();
+
+        counter.set_text(&format!("{marked} marked"));
+        counter.set_visible(is_multi() && marked > 0);
+    });
+}
+
 pub fn get_selected_item() -> Option<crate::protos::generated_proto::query::query_response::Item> {
     with_window(|w| {
         w.selection
@@ -1098,7 +1285,7 @@ pub fn set_global_keybind_hints(actions: Vec<String>, provider: String) {
                 k.remove(&child);
             }
 
-            let providers = PROVIDERS.get().unwrap();
+            let providers = get_providers();
 
             while let Some(child) = k.first_child() {
                 k.remove(&child);
@@ -1149,7 +1336,7 @@ pub fn set_keybind_hint() {
             };
         };
 
-        let providers = PROVIDERS.get().unwrap();
+        let providers = get_providers();
 
         if let Some(p) = providers.get(&provider) {
             if !actions.is_empty() {
@@ -1399,7 +1586,7 @@ pub fn show_actions_menu(response: Option<crate::protos::generated_proto::query:
         w.items.remove_all();
         set_input_text("");
 
-        let providers = PROVIDERS.get().unwrap();
+        let providers = get_providers();
 
         let mut actions = item.actions.clone();
         let provider = &item.provider;
@@ -0,0 +1,205 @@
+//! Drives a walker service over a private D-Bus session bus with
+//! `gapplication action` and checks the effect of every action with the
+//! `get-state` command on `walker.sock`. Skipped if there is no display,
+//! `dbus-daemon` or `gapplication`.
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+const APP_ID: &str = "dev.benz.walker";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CONFIG: &str = r#"
+[providers.sets.test]
+default = ["files"]
+empty = ["files"]
+"#;
+
+/// Kills the child when dropped, so a failing test doesn't leave it running.
+struct Guard(Child);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+struct Session {
+    bus: String,
+    runtime: PathBuf,
+}
+
+impl Session {
+    fn action(&self, args: &[&str]) {
+        let status = Command::new("gapplication")
+            .arg("action")
+            .arg(APP_ID)
+            .args(args)
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.bus)
+            .stdout(Stdio::null())
+            .status()
+            .expect("couldn't run gapplication");
+
+        assert!(status.success(), "gapplication action {args:?} failed");
+    }
+
+    /// Ask walker for its state. `None` while the socket isn't up yet.
+    fn try_state(&self) -> Option<Value> {
+        let mut stream = UnixStream::connect(self.runtime.join("walker/walker.sock")).ok()?;
+        stream.write_all(b"{\"command\":\"get-state\"}\n").ok()?;
+        stream.shutdown(std::net::Shutdown::Write).ok()?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).ok()?;
+
+        let resp: Value = serde_json::from_str(&line).ok()?;
+        assert_eq!(resp["ok"], true, "get-state failed: {line}");
+
+        Some(resp["state"].clone())
+    }
+
+    fn state(&self) -> Value {
+        self.try_state().expect("walker.sock doesn't answer")
+    }
+}
+
+fn has_command(name: &str) -> bool {
+    Command::new(name)
+        .arg("--help")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+fn start_bus() -> (Guard, String) {
+    let mut child = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address=1"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("couldn't start dbus-daemon");
+
+    let mut address = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut address)
+        .expect("dbus-daemon didn't print its address");
+
+    (Guard(child), address.trim().to_string())
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("walker-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// An `elephant` that knows no providers. Walker doesn't open without one, the
+/// connection to it fails and is retried in the background.
+fn fake_elephant(dir: &Path) {
+    let path = dir.join("elephant");
+    fs::write(&path, "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn actions_over_dbus() {
+    if env::var_os("WAYLAND_DISPLAY").is_none() && env::var_os("DISPLAY").is_none() {
+        eprintln!("skipped, no display");
+        return;
+    }
+
+    if !has_command("dbus-daemon") || !has_command("gapplication") {
+        eprintln!("skipped, dbus-daemon or gapplication missing");
+        return;
+    }
+
+    let (_bus_guard, bus) = start_bus();
+    let runtime = temp_dir("runtime");
+    let config = temp_dir("config");
+    let bin = temp_dir("bin");
+
+    fs::create_dir_all(config.join("walker")).unwrap();
+    fs::write(config.join("walker/config.toml"), CONFIG).unwrap();
+    fake_elephant(&bin);
+
+    let path = format!("{}:{}", bin.display(), env::var("PATH").unwrap_or_default());
+
+    let mut walker = Guard(
+        Command::new(env!("CARGO_BIN_EXE_walker"))
+            .arg("--gapplication-service")
+            .env("DBUS_SESSION_BUS_ADDRESS", &bus)
+            .env("XDG_RUNTIME_DIR", &runtime)
+            .env("XDG_CONFIG_HOME", &config)
+            .env("PATH", path)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("couldn't start walker"),
+    );
+
+    let session = Session { bus, runtime };
+
+    let start = Instant::now();
+    while session.try_state().is_none() {
+        assert!(
+            start.elapsed() < STARTUP_TIMEOUT,
+            "walker didn't open walker.sock"
+        );
+        assert!(
+            walker.0.try_wait().unwrap().is_none(),
+            "walker exited during startup"
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert_eq!(session.state()["visible"], false);
+
+    session.action(&["open-provider", "'files'"]);
+    let state = session.state();
+    assert_eq!(state["visible"], true);
+    assert_eq!(state["provider"], "files");
+
+    session.action(&["set-query", "'walker'"]);
+    assert_eq!(session.state()["query"], "walker");
+
+    session.action(&["open-set", "'test'"]);
+    let state = session.state();
+    assert_eq!(state["set"], "test");
+    assert_eq!(state["provider"], "");
+
+    // unknown sets and themes are refused and change nothing
+    session.action(&["open-set", "'missing'"]);
+    assert_eq!(session.state()["set"], "test");
+
+    session.action(&["switch-theme", "'missing'"]);
+    assert_eq!(session.state()["theme"], "default");
+
+    session.action(&["switch-theme", "'default'"]);
+    let state = session.state();
+    assert_eq!(state["theme"], "default");
+    assert_eq!(state["visible"], true);
+
+    session.action(&["reload"]);
+    assert_eq!(session.state()["visible"], true);
+
+    session.action(&["close"]);
+    assert_eq!(session.state()["visible"], false);
+
+    assert!(
+        walker.0.try_wait().unwrap().is_none(),
+        "walker exited after the actions"
+    );
+
+    for dir in [&session.runtime, &config, &bin] {
+        let _ = fs::remove_dir_all(dir);
+    }
+}